@@ -1,44 +1,193 @@
 use actix_files::NamedFile;
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder, ResponseError};
-use chrono::{Datelike, Duration, Utc};
-use log::{error, info, warn};
+use actix_web::dev::Service;
+use actix_web::error::{ErrorNotFound, ErrorServiceUnavailable};
+use actix_web::http::header;
+use actix_web::rt::{self, signal};
+use actix_web::{
+    get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError, Result,
+};
+use log::{debug, error, info, warn};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
+use lib::access::{self, AccessLog, RotatingFile};
+use lib::aliases::{self, AliasApi, AuthorIds};
 use lib::books;
+use lib::catalog::{self, CatalogApi};
+use lib::compress::Compression;
+use lib::editions::{self, Detection, Edition};
+use lib::facets::{self, Facets, Lang, Sort, SortKey};
+use lib::fb2;
+use lib::i18n::Locale;
+use lib::import;
+use lib::layout;
+use lib::mail::{self, Mailer, Outbox, SmtpConfig};
+use lib::metrics::{Metrics, Timed};
+use lib::reader;
 use lib::search;
-use lib::opds::Feed;
+use lib::opds::{Entry, Feed, ATOM_TYPE, CATALOG_TYPE, ENTRY_TYPE};
+use lib::pages;
 use lib::statistic::StatisticApi;
+use lib::tls::{self, CertResolver};
+use lib::translit;
+use lib::urls::{BaseUrl, Urls};
+use lib::user::{TrustedProxies, User};
+use lib::watcher;
 use opds_api::OpdsApi;
 
+use std::collections::{HashMap, HashSet};
 use std::env::VarError;
 use std::fmt::{self, Display};
-use std::io;
+use std::io::{self, Read};
 use std::os::unix::fs::MetadataExt;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::{Arc, LockResult, Mutex, MutexGuard, OnceLock, PoisonError, RwLock};
+use std::time::Instant;
 
 const DEFAULT_ADDRESS: &'static str = "localhost";
 const DEFAULT_PORT: u16 = 8080;
 const DEFAULT_DATABASE: &'static str = "file:/lib.rus.ec/books.db?mode=ro";
 const DEFAULT_STATISTIC: &'static str = "file:statistic.db?mode=rwc";
+const DEFAULT_ALIASES: &str = "file:aliases.db?mode=rwc";
 const DEFAULT_LIBRARY: &'static str = "/lib.rus.ec";
+const DEFAULT_LOCALE: &str = "ru";
+const DEFAULT_ACCESS_LOG_FORMAT: &str = "text";
+const DEFAULT_ACCESS_LOG_SIZE: u64 = 10;
+const DEFAULT_COMPRESS_MIN_SIZE: u64 = 1024;
+const DEFAULT_SMTP_SECURITY: &str = "starttls";
+const DEFAULT_SMTP_FORMAT: &str = "fb2";
+const SETTING_EMAIL: &str = "email";
+const SETTING_LANG: &str = "lang";
+const RECENT_MAILS: u32 = 10;
+const RECENT_MAIL_MINUTES: u32 = 60;
+const DEFAULT_FEED_SIZE: u32 = 50;
+const DEFAULT_NEW_DAYS: u32 = 14;
+const PAGE_SIZE: u32 = 50;
+const RANDOM_BOOKS: usize = 20;
+const MAX_SUGGESTIONS: usize = 100;
+const MAX_TRANSLIT_NAMES: usize = 50;
+const READER_BOOKS: usize = 8;
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
 
 type AppCtx = web::Data<AppState>;
 
 struct AppState {
     api: Mutex<OpdsApi>,
+    catalog: Mutex<CatalogApi>,
     stat: Mutex<StatisticApi>,
+    aliases: Mutex<AliasApi>,
+    index: RwLock<books::Index>,
+    /// The books recently opened in the reader
+    opened: reader::Cache,
+    metrics: Metrics,
+    outbox: OnceLock<Outbox>,
+    /// The number of the latest books in the subscription feeds
+    feed_size: u32,
+    /// The number of days the books are shown as the new arrivals
+    new_days: u32,
+    /// The users allowed to merge the authors
+    admins: Vec<String>,
+    /// The token of the forms, the forms posted from other sites do not have it
+    csrf_token: String,
+    /// The books marked as deleted are not listed
+    hide_deleted: bool,
+    /// How the editions of the same book are collapsed in the lists
+    editions: Detection,
+    database: String,
     storage: PathBuf,
 }
 impl AppState {
-    pub fn new(api: OpdsApi, stat: StatisticApi, storage: PathBuf) -> Self {
-        Self {
-            api: Mutex::new(api),
+    pub fn new(
+        database: String,
+        stat: StatisticApi,
+        aliases: AliasApi,
+        storage: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let mut catalog = CatalogApi::try_from(&database)?;
+        index_names(&mut catalog);
+        Ok(Self {
+            api: Mutex::new(OpdsApi::try_from(&database)?),
+            catalog: Mutex::new(catalog),
             stat: Mutex::new(stat),
-            storage: storage,
-        }
+            aliases: Mutex::new(aliases),
+            index: RwLock::new(books::Index::load(&storage)?),
+            opened: reader::Cache::new(READER_BOOKS),
+            metrics: Metrics::new(),
+            outbox: OnceLock::new(),
+            feed_size: DEFAULT_FEED_SIZE,
+            new_days: DEFAULT_NEW_DAYS,
+            admins: Vec::new(),
+            csrf_token: random_token()?,
+            hide_deleted: false,
+            editions: Detection::default(),
+            database,
+            storage,
+        })
+    }
+
+    /// Reopen the catalog and rescan the library, the old catalog is kept on errors.
+    /// The locks are taken together so requests see either the old or the new state.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let api = OpdsApi::try_from(&self.database)?;
+        let mut catalog = CatalogApi::try_from(&self.database)?;
+        catalog.set_hide_deleted(self.hide_deleted);
+        index_names(&mut catalog);
+        let index = books::Index::load(&self.storage)?;
+
+        let mut api_guard = self.api.lock().map_err(|e| anyhow::anyhow!("{e}"))?;
+        let mut catalog_guard = self.catalog.lock().map_err(|e| anyhow::anyhow!("{e}"))?;
+        let mut index_guard = self.index.write().map_err(|e| anyhow::anyhow!("{e}"))?;
+        *api_guard = api;
+        *catalog_guard = catalog;
+        *index_guard = index;
+        self.opened.clear();
+        info!("The catalog {} has been reloaded", self.database);
+        Ok(())
+    }
+
+    /// Lock the catalog API, the time the lock is held is reported to the metrics
+    pub fn api(&self) -> LockResult<Timed<'_, MutexGuard<'_, OpdsApi>>> {
+        timed(&self.metrics, "api", self.api.lock())
+    }
+
+    /// Lock the catalog, the time the lock is held is reported to the metrics
+    pub fn catalog(&self) -> LockResult<Timed<'_, MutexGuard<'_, CatalogApi>>> {
+        timed(&self.metrics, "catalog", self.catalog.lock())
+    }
+
+    /// Returns true if the user may merge the authors
+    pub fn is_admin(&self, user: &User) -> bool {
+        !user.name().is_empty() && self.admins.iter().any(|admin| admin == user.name())
+    }
+
+    /// Returns the CRC32 of the book file, None if it is not found
+    pub fn book_crc32(&self, id: u32) -> Option<u32> {
+        let index = self.index.read().ok()?;
+        books::crc32(&index, id)
+            .inspect_err(|err| warn!("The book {id} is not hashed: {err}"))
+            .ok()
+            .flatten()
+    }
+
+    pub fn extract_book(&self, id: u32) -> io::Result<PathBuf> {
+        let index = self
+            .index
+            .read()
+            .map_err(|e| io::Error::other(format!("{e}")))?;
+        let started = Instant::now();
+        let path = books::extract_book(&index, id)?;
+        self.metrics.extraction(started.elapsed());
+        Ok(path)
     }
 }
+
+fn timed<'a, G>(
+    metrics: &'a Metrics,
+    db: &'static str,
+    lock: LockResult<G>,
+) -> LockResult<Timed<'a, G>> {
+    lock.map(|guard| metrics.timed(db, guard))
+        .map_err(|err| PoisonError::new(metrics.timed(db, err.into_inner())))
+}
 #[derive(Debug)]
 struct OpdsError(anyhow::Error);
 
@@ -60,6 +209,13 @@ async fn main() -> anyhow::Result<()> {
     std::env::set_var("RUST_BACKTRACE", "1");
     env_logger::init();
 
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("import") => return import(&args[2..]),
+        Some("update") => return update(&args[2..]),
+        _ => {}
+    }
+
     let address = get_env("FB2S_ADDRESS", DEFAULT_ADDRESS);
     info!("FB2S_ADDRESS: {address}");
 
@@ -75,18 +231,210 @@ async fn main() -> anyhow::Result<()> {
     let statistic = get_env("FB2S_STATISTIC", DEFAULT_STATISTIC);
     info!("FB2S_STATISTIC: {statistic}");
 
+    let aliases = get_env("FB2S_ALIASES", DEFAULT_ALIASES);
+    info!("FB2S_ALIASES: {aliases}");
+
+    let admins = get_env("FB2S_ADMINS", "")
+        .split(',')
+        .map(str::trim)
+        .filter(|admin| !admin.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    info!("FB2S_ADMINS: {admins:?}");
+
+    let trusted_proxies = get_env("FB2S_TRUSTED_PROXY", "");
+    info!("FB2S_TRUSTED_PROXY: {trusted_proxies}");
+    let trusted_proxies = TrustedProxies::new(&trusted_proxies);
+    if trusted_proxies.is_empty() && !admins.is_empty() {
+        warn!("FB2S_ADMINS have no effect without FB2S_TRUSTED_PROXY");
+    }
+
+    let hide_deleted = get_env("FB2S_HIDE_DELETED", "false")
+        .parse::<bool>()
+        .unwrap_or_default();
+    info!("FB2S_HIDE_DELETED: {hide_deleted}");
+
+    let editions = Detection::from(get_env("FB2S_EDITIONS", "title").as_str());
+    info!("FB2S_EDITIONS: {editions:?}");
+
     let storage = PathBuf::from(get_env("FB2S_LIBRARY", DEFAULT_LIBRARY));
     info!("FB2S_LIBRARY: {}", storage.display());
 
-    let api = OpdsApi::try_from(&database)?;
+    let locale = Locale::from_tag(&get_env("FB2S_LOCALE", DEFAULT_LOCALE)).unwrap_or_default();
+    info!("FB2S_LOCALE: {locale:?}");
+
+    let access_format = get_env("FB2S_ACCESS_LOG_FORMAT", DEFAULT_ACCESS_LOG_FORMAT);
+    let access_format = access::Format::from(access_format.as_str());
+    info!("FB2S_ACCESS_LOG_FORMAT: {access_format:?}");
+
+    let access_file = get_env("FB2S_ACCESS_LOG", "");
+    info!("FB2S_ACCESS_LOG: {access_file}");
+
+    let access_size = get_env("FB2S_ACCESS_LOG_SIZE", &DEFAULT_ACCESS_LOG_SIZE.to_string())
+        .parse::<u64>()
+        .unwrap_or(DEFAULT_ACCESS_LOG_SIZE);
+    info!("FB2S_ACCESS_LOG_SIZE: {access_size} MB");
+
+    let access_file = match access_file.is_empty() {
+        true => None,
+        false => Some(RotatingFile::open(
+            PathBuf::from(access_file),
+            access_size * 1024 * 1024,
+        )?),
+    };
+    let access_log = AccessLog::new(access_format, access_file);
+
+    let compress_min_size = get_env(
+        "FB2S_COMPRESS_MIN_SIZE",
+        &DEFAULT_COMPRESS_MIN_SIZE.to_string(),
+    )
+    .parse::<u64>()
+    .unwrap_or(DEFAULT_COMPRESS_MIN_SIZE);
+    info!("FB2S_COMPRESS_MIN_SIZE: {compress_min_size} B");
+
+    let feed_size = get_env("FB2S_FEED_SIZE", &DEFAULT_FEED_SIZE.to_string())
+        .parse::<u32>()
+        .unwrap_or(DEFAULT_FEED_SIZE);
+    info!("FB2S_FEED_SIZE: {feed_size}");
+
+    let new_days = get_env("FB2S_NEW_DAYS", &DEFAULT_NEW_DAYS.to_string())
+        .parse::<u32>()
+        .unwrap_or(DEFAULT_NEW_DAYS);
+    info!("FB2S_NEW_DAYS: {new_days}");
+
+    let base_url = get_env("FB2S_BASE_URL", "");
+    info!("FB2S_BASE_URL: {base_url}");
+    let base_url = BaseUrl::new(&base_url);
+
+    let tls_cert = get_env("FB2S_TLS_CERT", "");
+    info!("FB2S_TLS_CERT: {tls_cert}");
+
+    let tls_key = get_env("FB2S_TLS_KEY", "");
+    info!("FB2S_TLS_KEY: {tls_key}");
+
+    let redirect_port = get_env("FB2S_REDIRECT_PORT", "").parse::<u16>().ok();
+    info!("FB2S_REDIRECT_PORT: {redirect_port:?}");
+
+    let smtp_host = get_env("FB2S_SMTP_HOST", "");
+    info!("FB2S_SMTP_HOST: {smtp_host}");
+
+    let smtp_port = get_env("FB2S_SMTP_PORT", "").parse::<u16>().ok();
+    info!("FB2S_SMTP_PORT: {smtp_port:?}");
+
+    let smtp_security = get_env("FB2S_SMTP_SECURITY", DEFAULT_SMTP_SECURITY);
+    let smtp_security = mail::Security::from(smtp_security.as_str());
+    info!("FB2S_SMTP_SECURITY: {smtp_security:?}");
+
+    let smtp_user = get_env("FB2S_SMTP_USER", "");
+    info!("FB2S_SMTP_USER: {smtp_user}");
+
+    let smtp_from = get_env("FB2S_SMTP_FROM", &smtp_user);
+    info!("FB2S_SMTP_FROM: {smtp_from}");
+
+    let smtp_format = get_env("FB2S_SMTP_FORMAT", DEFAULT_SMTP_FORMAT);
+    let smtp_format = mail::Format::from(smtp_format.as_str());
+    info!("FB2S_SMTP_FORMAT: {smtp_format:?}");
+
+    let smtp = SmtpConfig {
+        host: smtp_host,
+        port: smtp_port,
+        security: smtp_security,
+        username: smtp_user,
+        password: get_env("FB2S_SMTP_PASSWORD", ""),
+        from: smtp_from,
+        format: smtp_format,
+    };
+
+    let resolver = match (tls_cert.is_empty(), tls_key.is_empty()) {
+        (false, false) => {
+            let resolver = CertResolver::new(PathBuf::from(tls_cert), PathBuf::from(tls_key))?;
+            Some(Arc::new(resolver))
+        }
+        (true, true) => None,
+        _ => anyhow::bail!("Both FB2S_TLS_CERT and FB2S_TLS_KEY are required for HTTPS"),
+    };
+    let _tls_watcher = resolver.as_ref().and_then(|resolver| {
+        let reloader = resolver.clone();
+        watcher::watch_files(&resolver.files(), move || reloader.reload())
+            .inspect_err(|err| warn!("The certificate will not be watched: {err}"))
+            .ok()
+    });
+
     let stat = StatisticApi::try_from(&statistic)?;
+    let aliases = AliasApi::try_from(&aliases)?;
+    let mut state = AppState::new(database.clone(), stat, aliases, storage.clone())?;
+    state.feed_size = feed_size;
+    state.new_days = new_days;
+    state.admins = admins;
+    state.hide_deleted = hide_deleted;
+    state.editions = editions;
+    if let Ok(catalog) = state.catalog.get_mut() {
+        catalog.set_hide_deleted(hide_deleted);
+    }
+    let ctx = web::Data::new(state);
+
+    if !smtp.host.is_empty() {
+        let mailer = Mailer::new(&smtp)?;
+        let sender = ctx.clone();
+        let outbox = mail::spawn(mailer, StatisticApi::try_from(&statistic)?, move |id| {
+            sender.extract_book(id)
+        });
+        let _ = ctx.outbox.set(outbox);
+    }
 
-    let ctx = web::Data::new(AppState::new(api, stat, storage));
+    let reloader = ctx.clone();
+    let db_path = watcher::database_path(&database);
+    let _watcher = watcher::watch(&storage, &db_path, move || {
+        if let Err(err) = reloader.reload() {
+            error!("Reload failed: {err}");
+        }
+    })
+    .inspect_err(|err| warn!("The library will not be watched: {err}"))
+    .ok();
+
+    let reloader = ctx.clone();
+    rt::spawn(async move {
+        let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => return error!("SIGHUP handler: {err}"),
+        };
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP received");
+            let reloader = reloader.clone();
+            match rt::task::spawn_blocking(move || reloader.reload()).await {
+                Ok(Err(err)) => error!("Reload failed: {err}"),
+                Err(err) => error!("Reload failed: {err}"),
+                Ok(Ok(())) => {}
+            }
+        }
+    });
 
-    info!("OPDS Server will ready at http://{address}:{port}/opds");
-    HttpServer::new(move || {
+    let scheme = if resolver.is_some() { "https" } else { "http" };
+    info!("OPDS Server will ready at {scheme}://{address}:{port}/opds");
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(ctx.clone())
+            .app_data(locale)
+            .app_data(base_url.clone())
+            .app_data(trusted_proxies.clone())
+            .wrap_fn(|req, srv| {
+                let started = Instant::now();
+                let method = req.method().to_string();
+                let response = srv.call(req);
+                async move {
+                    let res = response.await?;
+                    if let Some(ctx) = res.request().app_data::<AppCtx>() {
+                        let route = res.request().match_pattern();
+                        let route = route.as_deref().unwrap_or("unmatched");
+                        let status = res.status().as_u16();
+                        ctx.metrics
+                            .request(route, &method, status, started.elapsed());
+                    }
+                    Ok(res)
+                }
+            })
+            .wrap(access_log.clone())
+            .wrap(Compression::new(compress_min_size))
             .service(opds)
             // Books by Authors
             .service(opds_authors)
@@ -102,141 +450,288 @@ async fn main() -> anyhow::Result<()> {
             .service(opds_genres)
             .service(opds_genres_by_meta)
             .service(opds_genre_by_id)
+            .service(opds_genre_by_id_and_year)
             // Books
             .service(opds_books_by_author_and_serie)
             .service(opds_books_by_author_nonserie)
+            .service(opds_genres_by_author)
             .service(opds_books_by_author_and_genre)
+            .service(opds_books_by_author)
             .service(opds_books_by_author_alphabet)
-            .service(opds_books_by_author_datesort)
+            .service(opds_books_by_author_added)
             .service(opds_books_by_serie)
             .service(opds_books_by_genre_year_month)
             .service(opds_book_upload)
+            .service(opds_book_info)
+            .service(opds_book_send)
+            // Built-in Reader
+            .service(read_book)
+            .service(read_book_toc)
+            .service(read_book_page)
+            .service(read_book_send_confirm)
+            .service(read_book_send)
+            // Settings
+            .service(settings)
+            .service(settings_save)
+            .service(admin_authors)
+            .service(admin_authors_save)
+            // New Books
+            .service(opds_new)
+            .service(opds_new_by_group)
+            .service(opds_new_by_day)
+            // Random Books
+            .service(opds_random)
+            .service(opds_random_by_genre)
             // Favorite Books
             .service(opds_authors_favorits)
-    })
-    .bind((address.as_str(), port))?
-    .run()
-    .await
-    .map_err(anyhow::Error::from)
+            // Subscriptions
+            .service(feeds_genre)
+            .service(feeds_author)
+            // Monitoring
+            .service(service_health)
+            .service(service_ready)
+            .service(service_metrics)
+    });
+    let server = match resolver {
+        Some(resolver) => {
+            let config = tls::server_config(resolver)?;
+            server.bind_rustls_0_23((address.as_str(), port), config)?
+        }
+        None => server.bind((address.as_str(), port))?,
+    }
+    .run();
+
+    match redirect_port {
+        Some(redirect_port) => {
+            info!("Redirecting http://{address}:{redirect_port} to {scheme}://{address}:{port}");
+            let redirect = HttpServer::new(move || {
+                App::new()
+                    .app_data(web::Data::new(port))
+                    .default_service(web::to(redirect_to_https))
+            })
+            .bind((address.as_str(), redirect_port))?
+            .run();
+            futures::future::try_join(server, redirect).await?;
+            Ok(())
+        }
+        None => server.await.map_err(anyhow::Error::from),
+    }
+}
+
+/// Redirects the plain HTTP request to the same path on the HTTPS port
+async fn redirect_to_https(req: HttpRequest, port: web::Data<u16>) -> HttpResponse {
+    let info = req.connection_info();
+    let host = info.host();
+    // strip the port but not the part of IPv6 address like `[::1]`
+    let host = match host.rfind(':') {
+        Some(idx) if !host[idx..].contains(']') => &host[..idx],
+        _ => host,
+    };
+    let location = match **port {
+        443 => format!("https://{host}{}", req.uri()),
+        port => format!("https://{host}:{port}{}", req.uri()),
+    };
+    HttpResponse::MovedPermanently()
+        .append_header((header::LOCATION, location))
+        .finish()
 }
 
 #[get("/opds")]
-async fn opds() -> impl Responder {
-    info!("/opds");
-    let mut feed = Feed::new("Каталог");
-    feed.catalog("Поиск по авторам", "/opds/authors");
-    feed.catalog("Поиск по сериям", "/opds/series");
-    feed.catalog("Поиск по жанрам", "/opds/genres");
-    feed.catalog("Поиск по наименованиям", "/opds/titles");
-    feed.catalog("Любимые авторы 10 дей", "/opds/authors/favorits/days/10");
-    feed.catalog("Любимые авторы 30 дей ", "/opds/authors/favorits/days/30");
-    feed.catalog("Любимые авторы 90 дей ", "/opds/authors/favorits/days/90");
+async fn opds(locale: Locale) -> impl Responder {
+    debug!("/opds");
+    let mut feed = Feed::new(locale.tr("catalog"));
+    feed.catalog(locale.tr("search.authors"), "/opds/authors");
+    feed.catalog(locale.tr("search.series"), "/opds/series");
+    feed.catalog(locale.tr("search.genres"), "/opds/genres");
+    feed.catalog(locale.tr("search.titles"), "/opds/titles");
+    feed.catalog(locale.tr("new.books"), "/opds/new");
+    feed.catalog(locale.tr("random.books"), "/opds/random");
+    for days in [10, 30, 90] {
+        let title = locale.tr_args("favorites", &[("days", days.to_string())]);
+        let link = format!("/opds/authors/favorits/days/{days}");
+        feed.catalog(title, link);
+    }
     feed.format()
 }
 
 #[get("/opds/authors")]
-async fn opds_authors(ctx: AppCtx) -> impl Responder {
-    info!("/opds/authors");
+async fn opds_authors(ctx: AppCtx, locale: Locale) -> impl Responder {
+    debug!("/opds/authors");
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Поиск книг по авторам");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("authors.search"));
+        feed.catalog(locale.tr("home"), "/opds");
         let all = String::from("");
         let patterns = api.authors_next_char_by_prefix(&all).map_err(OpdsError)?;
-        for prefix in patterns.into_iter() {
-            let title = format!("{prefix}...");
+        for prefix in search::merge_prefixes(patterns).into_iter() {
+            let title = format!("{}...", search::first_variant(&prefix));
             let encoded = utf8_percent_encode(prefix.as_str(), NON_ALPHANUMERIC).to_string();
             let link = format!("/opds/authors/mask/{encoded}");
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/authors/mask/{pattern}")]
-async fn opds_authors_by_mask(ctx: AppCtx, args: web::Path<String>) -> impl Responder {
+async fn opds_authors_by_mask(
+    ctx: AppCtx,
+    args: web::Path<String>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
     let pattern = args.into_inner();
-    info!("/opds/authors/mask/{pattern}");
+    debug!("/opds/authors/mask/{pattern}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Поиск книг по авторам");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("authors.search"));
+        feed.catalog(locale.tr("home"), "/opds");
 
         let fetcher = |s: &String| api.authors_next_char_by_prefix(s);
-        let (exact, tail) = search::search_by_mask(&pattern, fetcher).map_err(OpdsError)?;
+        let (exact, tail, corrected) =
+            search_names(&ctx, &pattern, fetcher, CatalogApi::last_names_index)
+                .map_err(OpdsError)?;
+        if let Some(corrected) = corrected {
+            let title = locale.tr_args("search.corrected", &[("query", corrected.clone())]);
+            let encoded = utf8_percent_encode(&corrected, NON_ALPHANUMERIC).to_string();
+            feed.catalog(title, format!("/opds/authors/mask/{encoded}"));
+        }
 
+        let mut authors = Vec::new();
         for name in exact.into_iter() {
-            let authors = api.authors_by_last_name(&name).map_err(OpdsError)?;
-            for author in authors.iter() {
-                let title = format!("[{author}]");
-                let link = format!(
-                    "/opds/author/id/{}/{}/{}",
-                    author.first_name.id, author.middle_name.id, author.last_name.id
-                );
-                feed.catalog(title, link);
-            }
+            authors.extend(api.authors_by_last_name(&name).map_err(OpdsError)?);
+        }
+        filter_authors(
+            &ctx,
+            &mut feed,
+            &facets,
+            &mut authors,
+            |author| {
+                (
+                    author.first_name.id,
+                    author.middle_name.id,
+                    author.last_name.id,
+                )
+            },
+            locale,
+        )
+        .map_err(OpdsError)?;
+        let authors = canonical_authors(&ctx, &authors, |author| {
+            (
+                author.first_name.id,
+                author.middle_name.id,
+                author.last_name.id,
+            )
+        })
+        .map_err(OpdsError)?;
+        for ((fid, mid, lid), name) in authors.iter() {
+            let title = format!("[{name}]");
+            let link = format!("/opds/author/id/{fid}/{mid}/{lid}");
+            feed.catalog(title, link);
         }
         for prefix in tail.into_iter() {
-            let title = format!("{prefix}...");
+            let title = format!("{}...", search::first_variant(&prefix));
             let encoded = utf8_percent_encode(prefix.as_str(), NON_ALPHANUMERIC).to_string();
             let link = format!("/opds/authors/mask/{encoded}");
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/author/id/{fid}/{mid}/{lid}")]
-async fn opds_author_by_id(args: web::Path<(u32, u32, u32)>) -> impl Responder {
+async fn opds_author_by_id(
+    ctx: AppCtx,
+    args: web::Path<(u32, u32, u32)>,
+    locale: Locale,
+) -> impl Responder {
     let (fid, mid, lid) = args.into_inner();
-    info!("/opds/author/id/{fid}/{mid}/{lid}");
+    debug!("/opds/author/id/{fid}/{mid}/{lid}");
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+    let (fid, mid, lid) = group[0];
 
     let ids = &format!("{fid}/{mid}/{lid}");
-    let mut feed = Feed::new("Книги автора");
-    feed.catalog("Cерии", &format!("/opds/series/author/{ids}"));
+    let mut feed = Feed::new(locale.tr("author.books"));
+    feed.catalog(
+        locale.tr("author.series"),
+        &format!("/opds/series/author/{ids}"),
+    );
     feed.catalog(
-        "Книги без серий",
+        locale.tr("author.nonserie"),
         &format!("/opds/books/author/nonserie/{ids}"),
     );
-    feed.catalog("Книги жанрам", &format!("/opds/books/author/genre/{ids}"));
     feed.catalog(
-        "Книги алфавиту",
-        &format!("/opds/books/author/alphabet/{ids}"),
+        locale.tr("author.genres"),
+        &format!("/opds/books/author/genre/{ids}"),
+    );
+    feed.catalog(
+        locale.tr("author.alphabet"),
+        &format!("/opds/books/author/{ids}?sort=title"),
     );
-    feed.catalog("Книги по дате", &format!("/opds/books/author/added/{ids}"));
+    feed.catalog(
+        locale.tr("author.added"),
+        &format!("/opds/books/author/{ids}?sort=added"),
+    );
+    let subscription = format!("/feeds/author/{ids}.atom");
+    let title = locale.tr("feeds.subscribe");
+    feed.link("alternate", title, &subscription, ATOM_TYPE);
 
     feed.format()
 }
 
 #[get("/opds/series/author/{fid}/{mid}/{lid}")]
-async fn opds_series_by_author(ctx: AppCtx, args: web::Path<(u32, u32, u32)>) -> impl Responder {
+async fn opds_series_by_author(
+    ctx: AppCtx,
+    args: web::Path<(u32, u32, u32)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
     let (fid, mid, lid) = args.into_inner();
-    info!("/opds/series/author/{fid}/{mid}/{lid}");
+    debug!("/opds/series/author/{fid}/{mid}/{lid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+    let (fid, mid, lid) = group[0];
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Серии автора");
-        feed.catalog("[Home]", "/opds");
-        let series = api.series_by_author_ids(fid, mid, lid).map_err(OpdsError)?;
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("series.author"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut series = Vec::new();
+        for &(fid, mid, lid) in group.iter() {
+            series.extend(api.series_by_author_ids(fid, mid, lid).map_err(OpdsError)?);
+        }
+        retain_unique(&mut series, |serie| serie.id);
+        filter_series(
+            &ctx,
+            &mut feed,
+            &facets,
+            &mut series,
+            |serie| serie.id,
+            locale,
+        )
+        .map_err(OpdsError)?;
         for serie in series.iter() {
             let title = format!("{serie}");
             let link = format!("/opds/serie/books/id/{}/{}/{}/{}", fid, mid, lid, serie.id);
             feed.catalog(title, link);
         }
         if series.is_empty() {
-            let title = format!("Вернуться к автору");
+            let title = String::from(locale.tr("author.back"));
             let link = format!("/opds/author/id/{}/{}/{}", fid, mid, lid);
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
@@ -246,197 +741,327 @@ async fn opds_series_by_author(ctx: AppCtx, args: web::Path<(u32, u32, u32)>) ->
 async fn opds_books_by_author_nonserie(
     ctx: AppCtx,
     args: web::Path<(u32, u32, u32)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
 ) -> impl Responder {
     let (fid, mid, lid) = args.into_inner();
-    info!("/opds/books/author/nonserie/{fid}/{mid}/{lid}");
+    debug!("/opds/books/author/nonserie/{fid}/{mid}/{lid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+    let (fid, mid, lid) = group[0];
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Книги без серий");
-        feed.catalog("[Home]", "/opds");
-        let books = api
-            .books_by_author_ids_without_serie(fid, mid, lid)
-            .map_err(OpdsError)?;
-        for book in books.iter() {
-            let title = format!("{book}");
-            let link = format!("/opds/book/id/{}", book.id);
-            feed.book(title, link);
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("books.nonserie"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut ids = Vec::new();
+        for &(fid, mid, lid) in group.iter() {
+            let books = api
+                .books_by_author_ids_without_serie(fid, mid, lid)
+                .map_err(OpdsError)?;
+            ids.extend(books.iter().map(|book| book.id));
         }
-        if books.is_empty() {
-            let title = format!("Вернуться к автору");
+        retain_unique(&mut ids, |id| *id);
+        let count = add_books(&ctx, &mut feed, &facets, &ids, locale).map_err(OpdsError)?;
+        if count == 0 {
+            let title = String::from(locale.tr("author.back"));
             let link = format!("/opds/author/id/{}/{}/{}", fid, mid, lid);
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
-#[get("/opds/books/author/genre/{genre}")]
-async fn opds_books_by_author_and_genre(ctx: AppCtx, args: web::Path<u32>) -> impl Responder {
-    let genre = args.into_inner();
-    info!("/opds/books/author/genre/{genre}");
+#[get("/opds/books/author/genre/{fid}/{mid}/{lid}")]
+async fn opds_genres_by_author(
+    ctx: AppCtx,
+    args: web::Path<(u32, u32, u32)>,
+    locale: Locale,
+) -> impl Responder {
+    let (fid, mid, lid) = args.into_inner();
+    debug!("/opds/books/author/genre/{fid}/{mid}/{lid}");
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+    let (fid, mid, lid) = group[0];
 
     let mut feed;
-    if let Ok(_api) = ctx.api.lock() {
-        feed = Feed::new("Книги по жанрам");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(catalog) = ctx.catalog() {
+        feed = Feed::new(locale.tr("books.genres"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut genres: Vec<(catalog::Genre, u32)> = Vec::new();
+        for &(fid, mid, lid) in group.iter() {
+            let counts = catalog
+                .genres_by_author_ids(fid, mid, lid)
+                .map_err(OpdsError)?;
+            for (genre, count) in counts.into_iter() {
+                match genres.iter_mut().find(|(known, _)| known.id == genre.id) {
+                    Some((_, total)) => *total += count,
+                    None => genres.push((genre, count)),
+                }
+            }
+        }
+        genres.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        for (genre, count) in genres.iter() {
+            let title = format!("{} ({count})", genre.name);
+            let link = format!(
+                "/opds/books/author/genre/{}/{}/{}/{}",
+                fid, mid, lid, genre.id
+            );
+            feed.catalog(title, link);
+        }
+        if genres.is_empty() {
+            let title = String::from(locale.tr("author.back"));
+            let link = format!("/opds/author/id/{}/{}/{}", fid, mid, lid);
+            feed.catalog(title, link);
+        }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
-#[get("/opds/books/author/alphabet/{fid}/{mid}/{lid}")]
-async fn opds_books_by_author_alphabet(
+#[get("/opds/books/author/genre/{fid}/{mid}/{lid}/{gid}")]
+async fn opds_books_by_author_and_genre(
     ctx: AppCtx,
-    args: web::Path<(u32, u32, u32)>,
+    args: web::Path<(u32, u32, u32, u32)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
 ) -> impl Responder {
-    let (fid, mid, lid) = args.into_inner();
-    info!("/opds/books/author/alphabet/{fid}/{mid}/{lid}");
+    let (fid, mid, lid, gid) = args.into_inner();
+    debug!("/opds/books/author/genre/{fid}/{mid}/{lid}/{gid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+    let (fid, mid, lid) = group[0];
+
+    // the empty list would look like the author has no books of the genre
+    let catalog = ctx
+        .catalog()
+        .map_err(|_| ErrorServiceUnavailable(locale.tr("error.lock")))?;
+    let mut in_genre = HashSet::new();
+    for &(fid, mid, lid) in group.iter() {
+        let ids = catalog
+            .books_ids_by_author_ids_and_genre_id(fid, mid, lid, gid)
+            .map_err(OpdsError)?;
+        in_genre.extend(ids);
+    }
+    drop(catalog);
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Книги по алфавиту");
-        feed.catalog("[Home]", "/opds");
-        let books = api.books_by_author_ids(fid, mid, lid).map_err(OpdsError)?;
-        for book in books.iter() {
-            let title = format!("{book}");
-            let link = format!("/opds/book/id/{}", book.id);
-            feed.book(title, link);
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("books.author.genre"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut ids = Vec::new();
+        for &(fid, mid, lid) in group.iter() {
+            let books = api.books_by_author_ids(fid, mid, lid).map_err(OpdsError)?;
+            ids.extend(books.iter().map(|book| book.id));
         }
-        if books.is_empty() {
-            let title = format!("Вернуться к автору");
+        ids.retain(|id| in_genre.contains(id));
+        retain_unique(&mut ids, |id| *id);
+        let count = add_books(&ctx, &mut feed, &facets, &ids, locale).map_err(OpdsError)?;
+        if count == 0 {
+            let title = String::from(locale.tr("author.back"));
             let link = format!("/opds/author/id/{}/{}/{}", fid, mid, lid);
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
+#[get("/opds/books/author/alphabet/{fid}/{mid}/{lid}")]
+async fn opds_books_by_author_alphabet(
+    args: web::Path<(u32, u32, u32)>,
+    facets: Facets,
+    urls: Urls,
+) -> HttpResponse {
+    let (fid, mid, lid) = args.into_inner();
+    debug!("/opds/books/author/alphabet/{fid}/{mid}/{lid}");
+    author_books_moved((fid, mid, lid), facets, Sort::Title, &urls)
+}
+
 #[get("/opds/books/author/added/{fid}/{mid}/{lid}")]
-async fn opds_books_by_author_datesort(
+async fn opds_books_by_author_added(
+    args: web::Path<(u32, u32, u32)>,
+    facets: Facets,
+    urls: Urls,
+) -> HttpResponse {
+    let (fid, mid, lid) = args.into_inner();
+    debug!("/opds/books/author/added/{fid}/{mid}/{lid}");
+    author_books_moved((fid, mid, lid), facets, Sort::Added, &urls)
+}
+
+/// Redirect the former routes of the author's books by title and by date to the sort facets
+fn author_books_moved(
+    (fid, mid, lid): (u32, u32, u32),
+    mut facets: Facets,
+    sort: Sort,
+    urls: &Urls,
+) -> HttpResponse {
+    facets.path = format!("/opds/books/author/{fid}/{mid}/{lid}");
+    HttpResponse::MovedPermanently()
+        .append_header((header::LOCATION, urls.url(&facets.with_sort(sort))))
+        .finish()
+}
+
+#[get("/opds/books/author/{fid}/{mid}/{lid}")]
+async fn opds_books_by_author(
     ctx: AppCtx,
     args: web::Path<(u32, u32, u32)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
 ) -> impl Responder {
     let (fid, mid, lid) = args.into_inner();
-    info!("/opds/books/author/added/{fid}/{mid}/{lid}");
+    debug!("/opds/books/author/{fid}/{mid}/{lid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+    let (fid, mid, lid) = group[0];
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Книги по дате поступления");
-        feed.catalog("[Home]", "/opds");
-        let mut books = api.books_by_author_ids(fid, mid, lid).map_err(OpdsError)?;
-        books.sort_by(|a, b| b.added.cmp(&a.added));
-        for book in books.iter() {
-            let title = format!("{book}");
-            let link = format!("/opds/book/id/{}", book.id);
-            feed.book(title, link);
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("author.books"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut ids = Vec::new();
+        for &(fid, mid, lid) in group.iter() {
+            let books = api.books_by_author_ids(fid, mid, lid).map_err(OpdsError)?;
+            ids.extend(books.iter().map(|book| book.id));
         }
-        if books.is_empty() {
-            let title = format!("Вернуться к автору");
+        retain_unique(&mut ids, |id| *id);
+        let count = add_books(&ctx, &mut feed, &facets, &ids, locale).map_err(OpdsError)?;
+        if count == 0 {
+            let title = String::from(locale.tr("author.back"));
             let link = format!("/opds/author/id/{}/{}/{}", fid, mid, lid);
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/series")]
-async fn opds_series(ctx: AppCtx) -> impl Responder {
-    info!("/opds/series");
+async fn opds_series(ctx: AppCtx, locale: Locale) -> impl Responder {
+    debug!("/opds/series");
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Поиск книг по сериям");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("series.search"));
+        feed.catalog(locale.tr("home"), "/opds");
         let all = String::from("");
         let patterns = api.series_next_char_by_prefix(&all).map_err(OpdsError)?;
-        for prefix in patterns.into_iter() {
-            let title = format!("{prefix}...");
+        for prefix in search::merge_prefixes(patterns).into_iter() {
+            let title = format!("{}...", search::first_variant(&prefix));
             let encoded = utf8_percent_encode(prefix.as_str(), NON_ALPHANUMERIC).to_string();
             let link = format!("/opds/series/mask/{encoded}");
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/series/mask/{pattern}")]
-async fn opds_series_by_mask(ctx: AppCtx, args: web::Path<String>) -> impl Responder {
+async fn opds_series_by_mask(
+    ctx: AppCtx,
+    args: web::Path<String>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
     let pattern = args.into_inner();
-    info!("/opds/series/mask/{pattern}");
+    debug!("/opds/series/mask/{pattern}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Поиск книг по сериям");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("series.search"));
+        feed.catalog(locale.tr("home"), "/opds");
         let fetcher = |s: &String| api.series_next_char_by_prefix(s);
-        let (exact, tail) = search::search_by_mask(&pattern, fetcher).map_err(OpdsError)?;
+        let (exact, tail, corrected) =
+            search_names(&ctx, &pattern, fetcher, CatalogApi::serie_names_index)
+                .map_err(OpdsError)?;
+        if let Some(corrected) = corrected {
+            let title = locale.tr_args("search.corrected", &[("query", corrected.clone())]);
+            let encoded = utf8_percent_encode(&corrected, NON_ALPHANUMERIC).to_string();
+            feed.catalog(title, format!("/opds/series/mask/{encoded}"));
+        }
 
+        let mut series = Vec::new();
         for name in exact.into_iter() {
-            let series = api.series_by_serie_name(&name).map_err(OpdsError)?;
-            for serie in series.iter() {
-                let title = format!("[{serie}]");
-                let link = format!("/opds/books/serie/id/{}", serie.id);
-                feed.catalog(title, link);
-            }
+            series.extend(api.series_by_serie_name(&name).map_err(OpdsError)?);
+        }
+        filter_series(
+            &ctx,
+            &mut feed,
+            &facets,
+            &mut series,
+            |serie| serie.id,
+            locale,
+        )
+        .map_err(OpdsError)?;
+        for serie in series.iter() {
+            let title = format!("[{serie}]");
+            let link = format!("/opds/books/serie/id/{}", serie.id);
+            feed.catalog(title, link);
         }
         for prefix in tail.into_iter() {
-            let title = format!("{prefix}...");
+            let title = format!("{}...", search::first_variant(&prefix));
             let encoded = utf8_percent_encode(prefix.as_str(), NON_ALPHANUMERIC).to_string();
             let link = format!("/opds/series/mask/{encoded}");
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/books/serie/id/{id}")]
-async fn opds_books_by_serie(ctx: AppCtx, args: web::Path<u32>) -> impl Responder {
+async fn opds_books_by_serie(
+    ctx: AppCtx,
+    args: web::Path<u32>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
     let id = args.into_inner();
-    info!("/opds/books/serie/id/{id}");
+    debug!("/opds/books/serie/id/{id}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    let facets = facets.sort_by_default(Sort::Serie);
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Книги в серии");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("books.serie"));
+        feed.catalog(locale.tr("home"), "/opds");
         let books = api.books_by_serie_id(id).map_err(OpdsError)?;
-        for book in books.iter() {
-            let title = format!("{book}");
-            let link = format!("/opds/book/id/{}", book.id);
-            feed.book(title, link);
-        }
+        let ids = books.iter().map(|book| book.id).collect::<Vec<_>>();
+        add_serie_books(&ctx, &mut feed, &facets, &ids, locale).map_err(OpdsError)?;
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/genres")]
-async fn opds_genres(ctx: AppCtx) -> impl Responder {
-    info!("/opds/genres");
+async fn opds_genres(ctx: AppCtx, locale: Locale) -> impl Responder {
+    debug!("/opds/genres");
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Жанры");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("genres"));
+        feed.catalog(locale.tr("home"), "/opds");
         let metas = api.meta_genres().map_err(OpdsError)?;
         for meta in metas.into_iter() {
             let encoded = utf8_percent_encode(meta.as_str(), NON_ALPHANUMERIC).to_string();
@@ -444,20 +1069,24 @@ async fn opds_genres(ctx: AppCtx) -> impl Responder {
             feed.catalog(meta, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/genres/meta/{meta}")]
-async fn opds_genres_by_meta(ctx: AppCtx, args: web::Path<String>) -> impl Responder {
+async fn opds_genres_by_meta(
+    ctx: AppCtx,
+    args: web::Path<String>,
+    locale: Locale,
+) -> impl Responder {
     let meta = args.into_inner();
-    info!("/opds/genres/meta/{meta}");
+    debug!("/opds/genres/meta/{meta}");
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Поджанры");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("genres.meta"));
         let genres = api.genres_by_meta(&meta).map_err(OpdsError)?;
         for genre in genres.into_iter() {
             let title = genre.value;
@@ -465,75 +1094,163 @@ async fn opds_genres_by_meta(ctx: AppCtx, args: web::Path<String>) -> impl Respo
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
 #[get("/opds/genre/id/{gid}")]
-async fn opds_genre_by_id(path: web::Path<u32>) -> impl Responder {
+async fn opds_genre_by_id(ctx: AppCtx, path: web::Path<u32>, locale: Locale) -> impl Responder {
     let gid = path.into_inner();
-    info!("/opds/genre/id/{gid}");
-
-    let mut feed = Feed::new(format!("Книги по жанру"));
-    feed.catalog("[Home]", "/opds");
-    feed.catalog("Список авторов", &format!("/opds/authors/genre/{gid}"));
-    feed.catalog("Список серий", &format!("/opds/series/genre/{gid}"));
-
-    let today = Utc::now().date_naive();
-    for i in 0..12 {
-        let date = today - Duration::days(30 * i);
-        let year = date.year();
-        let month = date.month();
-        let title = format!("Книги за {year} {} ", date.format("%B"));
-        let link = format!("/opds/books/genre/id/{gid}/year/{year}/month/{month}");
-        feed.catalog(title, link);
+    debug!("/opds/genre/id/{gid}");
+
+    let mut feed = Feed::new(locale.tr("genre.books"));
+    feed.catalog(locale.tr("home"), "/opds");
+    feed.catalog(
+        locale.tr("genre.authors"),
+        &format!("/opds/authors/genre/{gid}"),
+    );
+    feed.catalog(
+        locale.tr("genre.series"),
+        &format!("/opds/series/genre/{gid}"),
+    );
+    feed.catalog(
+        locale.tr("genre.random"),
+        &format!("/opds/random/genre/{gid}"),
+    );
+    let subscription = format!("/feeds/genre/{gid}.atom");
+    let title = locale.tr("feeds.subscribe");
+    feed.link("alternate", title, &subscription, ATOM_TYPE);
+
+    if let Ok(catalog) = ctx.catalog() {
+        let years = catalog.years_by_genre_id(gid).map_err(OpdsError)?;
+        for (year, count) in years.into_iter() {
+            let args = [("year", year.to_string())];
+            let title = format!("{} ({count})", locale.tr_args("genre.year", &args));
+            let link = format!("/opds/genre/id/{gid}/year/{year}");
+            feed.catalog(title, link);
+        }
+    }
+    feed.format()
+}
+
+#[get("/opds/genre/id/{gid}/year/{year}")]
+async fn opds_genre_by_id_and_year(
+    ctx: AppCtx,
+    args: web::Path<(u32, u32)>,
+    locale: Locale,
+) -> impl Responder {
+    let (gid, year) = args.into_inner();
+    debug!("/opds/genre/id/{gid}/year/{year}");
+
+    let mut feed;
+    if let Ok(catalog) = ctx.catalog() {
+        let args = [("year", year.to_string())];
+        feed = Feed::new(locale.tr_args("genre.year", &args));
+        feed.catalog(locale.tr("home"), "/opds");
+        let months = catalog
+            .months_by_genre_id_and_year(gid, year)
+            .map_err(OpdsError)?;
+        for (month, count) in months.into_iter() {
+            let args = [
+                ("month", locale.month(month).to_string()),
+                ("year", year.to_string()),
+            ];
+            let title = format!("{} ({count})", locale.tr_args("genre.month", &args));
+            let link = format!("/opds/books/genre/id/{gid}/year/{year}/month/{month}");
+            feed.catalog(title, link);
+        }
+    } else {
+        feed = Feed::new(locale.tr("error.lock"));
     }
+
     feed.format()
 }
 
 #[get("/opds/authors/genre/{gid}")]
-async fn opds_authors_by_genre(ctx: AppCtx, args: web::Path<u32>) -> impl Responder {
+async fn opds_authors_by_genre(
+    ctx: AppCtx,
+    args: web::Path<u32>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
     let gid = args.into_inner();
-    info!("/opds/authors/series/{gid}");
+    debug!("/opds/authors/series/{gid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Авторы по жанру");
-        feed.catalog("[Home]", "/opds");
-        let authors = api.authors_by_genre_id(gid).map_err(OpdsError)?;
-        for author in authors.into_iter() {
-            let title = format!("{author}");
-            let link = format!(
-                "/opds/author/id/{}/{}/{}",
-                author.first_name.id, author.middle_name.id, author.last_name.id
-            );
-            feed.catalog(title, link);
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("authors.genre"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut authors = api.authors_by_genre_id(gid).map_err(OpdsError)?;
+        filter_authors(
+            &ctx,
+            &mut feed,
+            &facets,
+            &mut authors,
+            |author| {
+                (
+                    author.first_name.id,
+                    author.middle_name.id,
+                    author.last_name.id,
+                )
+            },
+            locale,
+        )
+        .map_err(OpdsError)?;
+        let authors = canonical_authors(&ctx, &authors, |author| {
+            (
+                author.first_name.id,
+                author.middle_name.id,
+                author.last_name.id,
+            )
+        })
+        .map_err(OpdsError)?;
+        for ((fid, mid, lid), name) in authors.into_iter() {
+            let link = format!("/opds/author/id/{fid}/{mid}/{lid}");
+            feed.catalog(name, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
     feed.format()
 }
 
 #[get("/opds/series/genre/{gid}")]
-async fn opds_series_by_genre(ctx: AppCtx, args: web::Path<u32>) -> impl Responder {
+async fn opds_series_by_genre(
+    ctx: AppCtx,
+    args: web::Path<u32>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
     let gid = args.into_inner();
-    info!("/opds/series/genre/{gid}");
+    debug!("/opds/series/genre/{gid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Серии по жанру");
-        feed.catalog("[Home]", "/opds");
-        let series = api.series_by_genre_id(gid).map_err(OpdsError)?;
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("series.genre"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut series = api.series_by_genre_id(gid).map_err(OpdsError)?;
+        filter_series(
+            &ctx,
+            &mut feed,
+            &facets,
+            &mut series,
+            |serie| serie.id,
+            locale,
+        )
+        .map_err(OpdsError)?;
         for serie in series.iter() {
             let title = format!("{serie}");
             let link = format!("/opds/books/serie/id/{}", serie.id);
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
@@ -543,34 +1260,654 @@ async fn opds_series_by_genre(ctx: AppCtx, args: web::Path<u32>) -> impl Respond
 async fn opds_books_by_genre_year_month(
     ctx: AppCtx,
     args: web::Path<(u32, u16, u8)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
 ) -> impl Responder {
     let (gid, year, month) = args.into_inner();
-    info!("/opds/books/genre/id/{gid}/year/{year}/month/{month}");
+    debug!("/opds/books/genre/id/{gid}/year/{year}/month/{month}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Книги в серии по месяцам");
-        feed.catalog("[Home]", "/opds");
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("books.genre.month"));
+        feed.catalog(locale.tr("home"), "/opds");
         let date = format!("{}-{:02}-%", year, month);
         let books = api
             .books_by_genre_id_and_date(gid, date)
             .map_err(OpdsError)?;
+        let ids = books.iter().map(|book| book.id).collect::<Vec<_>>();
+        add_books(&ctx, &mut feed, &facets, &ids, locale).map_err(OpdsError)?;
+    } else {
+        feed = Feed::new(locale.tr("error.lock"));
+    }
+
+    feed.format()
+}
+
+#[get("/opds/new")]
+async fn opds_new(ctx: AppCtx, user: User, facets: Facets, locale: Locale) -> impl Responder {
+    debug!("/opds/new");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    new_books(&ctx, "day", 1, &facets, locale)
+}
+
+#[get("/opds/new/{group}/page/{page}")]
+async fn opds_new_by_group(
+    ctx: AppCtx,
+    args: web::Path<(String, u32)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
+    let (group, page) = args.into_inner();
+    debug!("/opds/new/{group}/page/{page}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    new_books(&ctx, &group, page, &facets, locale)
+}
+
+/// The page of the books added for the last days grouped by day or by genre
+fn new_books(
+    ctx: &AppState,
+    group: &str,
+    page: u32,
+    facets: &Facets,
+    locale: Locale,
+) -> Result<impl Responder> {
+    let page = page.max(1);
+    let by_genre = group == "genre";
+    let (group, other, other_title) = match by_genre {
+        true => ("genre", "day", "new.by_day"),
+        false => ("day", "genre", "new.by_genre"),
+    };
+    let lang = facets.lang();
+    let query = facets.query();
+
+    let mut feed;
+    if let Ok(catalog) = ctx.catalog() {
+        let days = ctx.new_days;
+        feed = Feed::new(locale.tr_args("new.title", &[("days", days.to_string())]));
+        feed.catalog(locale.tr("home"), "/opds");
+        let switch = format!("/opds/new/{other}/page/1{query}");
+        feed.catalog(locale.tr(other_title), &switch);
+
+        let langs = catalog.new_books_langs(days).map_err(OpdsError)?;
+        let total = langs.iter().map(|(_, count)| count).sum();
+        lang_facets(&mut feed, facets, &langs, Some(total), locale);
+
+        // one more book is requested to find out if there is the next page
+        let offset = page_offset(page)?;
+        let limit = PAGE_SIZE + 1;
+        let mut books = if by_genre {
+            let books = catalog
+                .new_books_by_genre(days, lang, offset, limit)
+                .map_err(OpdsError)?;
+            books
+                .into_iter()
+                .map(|(book, genre)| {
+                    let link = format!("/opds/genre/id/{}", genre.id);
+                    (book, genre.name, link)
+                })
+                .collect::<Vec<_>>()
+        } else {
+            let books = catalog
+                .new_books(days, lang, offset, limit)
+                .map_err(OpdsError)?;
+            books
+                .into_iter()
+                .map(|book| {
+                    let link = format!("/opds/new/day/{}/page/1{query}", book.added);
+                    let added = book.added.clone();
+                    (book, added, link)
+                })
+                .collect::<Vec<_>>()
+        };
+        let has_next = books.len() > PAGE_SIZE as usize;
+        books.truncate(PAGE_SIZE as usize);
+        // the page is sorted, the pages are in the order of the date
+        sort_books(
+            ctx,
+            &mut feed,
+            facets,
+            &mut books,
+            |(book, _, _)| book,
+            locale,
+        )
+        .map_err(OpdsError)?;
+
+        for (book, title, link) in books.iter() {
+            book_entry(&mut feed, book, locale).link("collection", title, link, CATALOG_TYPE);
+        }
+
+        let pages = format!("/opds/new/{group}/page");
+        paginate(&mut feed, &pages, &query, page, has_next, locale);
+    } else {
+        feed = Feed::new(locale.tr("error.lock"));
+    }
+
+    feed.format()
+}
+
+#[get("/opds/new/day/{date}/page/{page}")]
+async fn opds_new_by_day(
+    ctx: AppCtx,
+    args: web::Path<(String, u32)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
+    let (date, page) = args.into_inner();
+    debug!("/opds/new/day/{date}/page/{page}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    let date = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|_| ErrorNotFound(date.clone()))?;
+    let date = date.format("%Y-%m-%d").to_string();
+    let page = page.max(1);
+    let query = facets.query();
+
+    let mut feed;
+    if let Ok(catalog) = ctx.catalog() {
+        feed = Feed::new(locale.tr_args("new.day", &[("date", date.clone())]));
+        feed.catalog(locale.tr("home"), "/opds");
+        let back = format!("/opds/new/day/page/1{query}");
+        feed.catalog(locale.tr("new.books"), &back);
+
+        // one more book is requested to find out if there is the next page
+        let offset = page_offset(page)?;
+        let mut books = catalog
+            .books_added_on(&date, facets.lang(), offset, PAGE_SIZE + 1)
+            .map_err(OpdsError)?;
+        let has_next = books.len() > PAGE_SIZE as usize;
+        books.truncate(PAGE_SIZE as usize);
+        sort_books(&ctx, &mut feed, &facets, &mut books, |book| book, locale).map_err(OpdsError)?;
         for book in books.iter() {
-            let title = format!("{book}");
-            let link = format!("/opds/book/id/{}", book.id);
-            feed.book(title, link);
+            book_entry(&mut feed, book, locale);
         }
+
+        let pages = format!("/opds/new/day/{date}/page");
+        paginate(&mut feed, &pages, &query, page, has_next, locale);
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
 }
 
+/// Returns the offset of the first book on the page counted from 1,
+/// the page beyond the possible offsets is not found
+fn page_offset(page: u32) -> Result<u32> {
+    (page.max(1) - 1)
+        .checked_mul(PAGE_SIZE)
+        .ok_or_else(|| ErrorNotFound(page.to_string()))
+}
+
+#[get("/opds/random")]
+async fn opds_random(ctx: AppCtx, user: User, facets: Facets, locale: Locale) -> impl Responder {
+    debug!("/opds/random");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    random_books(&ctx, None, &facets, locale)
+}
+
+#[get("/opds/random/genre/{gid}")]
+async fn opds_random_by_genre(
+    ctx: AppCtx,
+    args: web::Path<u32>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
+    let gid = args.into_inner();
+    debug!("/opds/random/genre/{gid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    random_books(&ctx, Some(gid), &facets, locale)
+}
+
+/// The random books optionally of the genre and in the language,
+/// only the books which were not downloaded yet if it is requested
+fn random_books(
+    ctx: &AppState,
+    gid: Option<u32>,
+    facets: &Facets,
+    locale: Locale,
+) -> Result<impl Responder> {
+    let mut feed;
+    if let Ok(catalog) = ctx.catalog() {
+        feed = Feed::new(locale.tr("random.books"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let langs = catalog.langs(gid).map_err(OpdsError)?;
+        let total = langs.iter().map(|(_, count)| count).sum();
+        lang_facets(&mut feed, facets, &langs, Some(total), locale);
+        let group = locale.tr("facet.fresh");
+        for (fresh, title) in [(false, "facet.fresh.all"), (true, "facet.fresh.only")] {
+            let href = facets.with_fresh(fresh);
+            feed.facet(group, locale.tr(title), &href, None, facets.fresh == fresh);
+        }
+
+        // the downloads are checked only for the picked books
+        let stat = ctx.stat.lock().ok().filter(|_| facets.fresh);
+        let mut books = catalog
+            .random_books(RANDOM_BOOKS, gid, facets.lang(), |id| match &stat {
+                Some(stat) => stat.is_downloaded(id).unwrap_or(false),
+                None => false,
+            })
+            .map_err(OpdsError)?;
+        drop(stat);
+        sort_books(ctx, &mut feed, facets, &mut books, |book| book, locale).map_err(OpdsError)?;
+        for book in books.iter() {
+            book_entry(&mut feed, book, locale);
+        }
+        let refresh = format!("{}{}", facets.path, facets.query());
+        feed.catalog(locale.tr("random.more"), &refresh);
+    } else {
+        feed = Feed::new(locale.tr("error.lock"));
+    }
+
+    feed.format()
+}
+
+/// Add the links to the pages `{base}/{page}{query}` around the current one,
+/// the next page is also added as the entry for the clients ignoring the feed links
+fn paginate(feed: &mut Feed, base: &str, query: &str, page: u32, has_next: bool, locale: Locale) {
+    if page > 1 {
+        let first = format!("{base}/1{query}");
+        feed.link("first", locale.tr("page.first"), &first, CATALOG_TYPE);
+        let previous = format!("{base}/{}{query}", page - 1);
+        let title = locale.tr("page.previous");
+        feed.link("previous", title, &previous, CATALOG_TYPE);
+    }
+    if has_next {
+        let next = format!("{base}/{}{query}", page + 1);
+        feed.link("next", locale.tr("page.next"), &next, CATALOG_TYPE);
+        feed.catalog(locale.tr("page.next"), &next);
+    }
+}
+
+/// Add the acquisition entry of the catalog book to the feed
+fn book_entry<'a>(feed: &'a mut Feed, book: &catalog::Book, locale: Locale) -> &'a mut Entry {
+    let link = format!("/opds/book/id/{}", book.id);
+    let entry = feed.book(book.to_string(), link);
+    entry.authors = book.authors.clone();
+    if let Some(serie) = &book.serie {
+        let content = locale.tr_args("book.serie", &[("serie", serie.to_string())]);
+        entry.content = Some(content);
+        entry.serie = Some((serie.name.clone(), serie.num));
+    }
+    if !book.lang.is_empty() {
+        entry.dc.push(("language", book.lang.clone()));
+    }
+    let info = format!("/opds/book/info/{}", book.id);
+    entry.link("alternate", locale.tr("book.info.link"), &info, ENTRY_TYPE)
+}
+
+/// Returns the canonical author of the ids followed by its aliases
+fn author_group(ctx: &AppState, ids: AuthorIds) -> anyhow::Result<Vec<AuthorIds>> {
+    match ctx.aliases.lock() {
+        Ok(aliases) => aliases.group(ids),
+        Err(_) => Ok(vec![ids]),
+    }
+}
+
+/// Returns the canonical authors of the listed ones with their names,
+/// the aliases of the same author are listed once in place of the first one
+fn canonical_authors<T, F>(
+    ctx: &AppState,
+    authors: &[T],
+    ids: F,
+) -> anyhow::Result<Vec<(AuthorIds, String)>>
+where
+    T: Display,
+    F: Fn(&T) -> AuthorIds,
+{
+    let canonical = match ctx.aliases.lock() {
+        Ok(aliases) => authors
+            .iter()
+            .map(|author| aliases.canonical(ids(author)))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        Err(_) => authors.iter().map(&ids).collect(),
+    };
+
+    let mut names = Vec::new();
+    for (author, canonical) in authors.iter().zip(canonical) {
+        let name = match ids(author) == canonical {
+            true => Some(author.to_string()),
+            false => match ctx.catalog() {
+                Ok(catalog) => catalog.author_name_by_ids(canonical.0, canonical.1, canonical.2)?,
+                Err(_) => None,
+            },
+        };
+        names.push((canonical, name.unwrap_or_else(|| author.to_string())));
+    }
+    retain_unique(&mut names, |(ids, _)| *ids);
+    Ok(names)
+}
+
+/// Keep the first of the items with the same key
+fn retain_unique<T, K, F>(items: &mut Vec<T>, key: F)
+where
+    K: Eq + std::hash::Hash,
+    F: Fn(&T) -> K,
+{
+    let mut seen = HashSet::new();
+    items.retain(|item| seen.insert(key(item)));
+}
+
+/// Returns the complete names and the prefixes found by the mask search, the names
+/// in the other script which match the transliterated pattern are added to the complete
+/// ones. If nothing is found, the pattern typed in the other keyboard layout is searched
+/// and returned as corrected.
+fn search_names<F, N>(
+    ctx: &AppState,
+    pattern: &str,
+    fetcher: F,
+    names: N,
+) -> anyhow::Result<(Vec<String>, Vec<String>, Option<String>)>
+where
+    F: Fn(&String) -> anyhow::Result<Vec<String>>,
+    N: Fn(&CatalogApi) -> &translit::Index,
+{
+    let (mut exact, tail) = search::search_by_mask(pattern, &fetcher)?;
+    for name in translit_names(ctx, pattern, &names) {
+        if !exact.contains(&name) {
+            exact.push(name);
+        }
+    }
+    if !exact.is_empty() || !tail.is_empty() {
+        return Ok((exact, tail, None));
+    }
+    for corrected in layout::variants(search::first_variant(pattern)) {
+        let (exact, tail) = search::search_by_mask(corrected.as_str(), &fetcher)?;
+        if !exact.is_empty() || !tail.is_empty() {
+            debug!("{pattern} is corrected as {corrected}");
+            return Ok((exact, tail, Some(corrected)));
+        }
+    }
+    Ok((Vec::new(), Vec::new(), None))
+}
+
+/// Returns the names written in the other script which match the transliterated pattern
+fn translit_names<N>(ctx: &AppState, pattern: &str, names: N) -> Vec<String>
+where
+    N: Fn(&CatalogApi) -> &translit::Index,
+{
+    let pattern = search::first_variant(pattern);
+    match ctx.catalog() {
+        Ok(catalog) => names(&catalog).search(pattern, MAX_TRANSLIT_NAMES),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Returns the facets of the list with the user's default language if it is not selected
+fn user_facets(ctx: &AppState, user: &User, facets: Facets) -> anyhow::Result<Facets> {
+    let default = match ctx.stat.lock() {
+        Ok(stat) => stat.load_setting(user.name(), SETTING_LANG)?,
+        Err(_) => None,
+    };
+    Ok(facets.resolve(default.as_deref()))
+}
+
+/// Add the language facets with the number of items in each language,
+/// the items of the unknown language are counted only in `total`
+fn lang_facets(
+    feed: &mut Feed,
+    facets: &Facets,
+    langs: &[(String, u32)],
+    total: Option<u32>,
+    locale: Locale,
+) {
+    let group = locale.tr("facet.lang");
+    let selected = facets.lang();
+    let all = facets.with_lang(None);
+    let title = locale.tr("facet.lang.all");
+    feed.facet(group, title, &all, total, selected.is_none());
+    for (code, count) in langs.iter().filter(|(code, _)| !code.is_empty()) {
+        let href = facets.with_lang(Some(code));
+        let active = selected == Some(code.as_str());
+        feed.facet(group, locale.language(code), &href, Some(*count), active);
+    }
+    // the selected language is shown even if there are no items in it
+    if let Some(lang) = selected.filter(|lang| langs.iter().all(|(code, _)| code != lang)) {
+        let href = facets.with_lang(Some(lang));
+        feed.facet(group, locale.language(lang), &href, Some(0), true);
+    }
+}
+
+/// Keep the items in the selected language, the language facets are counted over all items
+fn filter_by_lang<T, F>(
+    feed: &mut Feed,
+    facets: &Facets,
+    items: &mut Vec<T>,
+    langs: F,
+    locale: Locale,
+) where
+    F: Fn(&T) -> Vec<String>,
+{
+    let langs = items.iter().map(langs).collect::<Vec<_>>();
+    let counts = facets::count_langs(langs.iter().map(Vec::as_slice));
+    lang_facets(feed, facets, &counts, Some(items.len() as u32), locale);
+    let mut keep = langs
+        .iter()
+        .map(|langs| facets::has_lang(langs, facets.lang()));
+    items.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// Add the books listed by ids to the feed, the books are read from the catalog,
+/// filtered by the language and sorted by the facets. Returns the number of added books.
+fn add_books(
+    ctx: &AppState,
+    feed: &mut Feed,
+    facets: &Facets,
+    ids: &[u32],
+    locale: Locale,
+) -> anyhow::Result<usize> {
+    let books = load_books(ctx, ids)?;
+    let editions = select_books(ctx, feed, facets, books, locale)?;
+    for edition in editions.iter() {
+        let entry = book_entry(feed, &edition.book, locale);
+        edition_links(entry, &edition.others, locale);
+    }
+    Ok(editions.len())
+}
+
+/// Add the books of the serie titled by their numbers like `#3. Title`.
+/// The books sorted by the serie are interleaved with the missing numbers of the serie.
+fn add_serie_books(
+    ctx: &AppState,
+    feed: &mut Feed,
+    facets: &Facets,
+    ids: &[u32],
+    locale: Locale,
+) -> anyhow::Result<usize> {
+    let books = load_books(ctx, ids)?;
+    // the books in other languages are not missing
+    let nums = books.iter().filter_map(|book| book.serie.as_ref());
+    let missing = catalog::missing_nums(nums.map(|serie| serie.num));
+    let editions = select_books(ctx, feed, facets, books, locale)?;
+
+    let missing = match facets.sort == Sort::Serie {
+        true => missing,
+        false => vec![],
+    };
+    let mut missing = missing.into_iter().peekable();
+    // the gaps are the notes without links
+    let prefix = format!("root{}:missing:", facets.path.replace('/', ":"));
+    let add_missing = |feed: &mut Feed, num: u32| {
+        let title = locale.tr_args("serie.missing", &[("num", num.to_string())]);
+        feed.push(Entry::text(format!("{prefix}{num}"), title));
+    };
+    for edition in editions.iter() {
+        let book = &edition.book;
+        let num = book.serie.as_ref().map(|serie| serie.num).unwrap_or(0);
+        if num > 0 {
+            while let Some(gap) = missing.next_if(|gap| *gap < num) {
+                add_missing(feed, gap);
+            }
+        }
+        let entry = book_entry(feed, book, locale);
+        if num > 0 {
+            entry.title = format!("#{num}. {}", book.title);
+        }
+        edition_links(entry, &edition.others, locale);
+    }
+    for gap in missing {
+        add_missing(feed, gap);
+    }
+    Ok(editions.len())
+}
+
+/// Returns the catalog books listed by ids in the same order
+fn load_books(ctx: &AppState, ids: &[u32]) -> anyhow::Result<Vec<catalog::Book>> {
+    match ctx.catalog() {
+        Ok(catalog) => catalog.books_by_ids(ids),
+        Err(_) => Ok(vec![]),
+    }
+}
+
+/// Returns the books in the selected language sorted by the facets,
+/// the editions of the same book are collapsed into the first one
+fn select_books(
+    ctx: &AppState,
+    feed: &mut Feed,
+    facets: &Facets,
+    mut books: Vec<catalog::Book>,
+    locale: Locale,
+) -> anyhow::Result<Vec<Edition>> {
+    filter_by_lang(
+        feed,
+        facets,
+        &mut books,
+        |book| vec![book.lang.clone()],
+        locale,
+    );
+    sort_books(ctx, feed, facets, &mut books, |book| book, locale)?;
+    let editions = match ctx.editions {
+        Detection::Off => books.into_iter().map(Edition::from).collect(),
+        Detection::Title => editions::collapse(books, |_| None),
+        Detection::Hash => editions::collapse(books, |book| ctx.book_crc32(book.id)),
+    };
+    Ok(editions)
+}
+
+/// Add the download links of the other editions of the book
+fn edition_links(entry: &mut Entry, others: &[catalog::Book], locale: Locale) {
+    for other in others.iter() {
+        let args = [
+            ("added", other.added.clone()),
+            ("size", (other.size / 1024).to_string()),
+        ];
+        let title = locale.tr_args("book.edition", &args);
+        let link = format!("/opds/book/id/{}", other.id);
+        entry.link(ACQUISITION_REL, &title, &link, "application/fb2+zip");
+    }
+}
+
+/// Sort the items by the selected order of their books and add the sort facets
+fn sort_books<T, F>(
+    ctx: &AppState,
+    feed: &mut Feed,
+    facets: &Facets,
+    items: &mut [T],
+    book: F,
+    locale: Locale,
+) -> anyhow::Result<()>
+where
+    F: Fn(&T) -> &catalog::Book,
+{
+    let group = locale.tr("facet.sort");
+    for sort in Sort::ALL {
+        let title = match sort {
+            Sort::Default | Sort::Title => locale.tr("sort.title"),
+            Sort::Added => locale.tr("sort.added"),
+            Sort::Serie => locale.tr("sort.serie"),
+            Sort::Size => locale.tr("sort.size"),
+            Sort::Popular => locale.tr("sort.popular"),
+        };
+        let href = facets.with_sort(sort);
+        feed.facet(group, title, &href, None, facets.sort == sort);
+    }
+
+    let mut downloads = HashMap::new();
+    if facets.sort == Sort::Popular {
+        let ids = items.iter().map(|item| book(item).id).collect::<Vec<_>>();
+        if let Ok(stat) = ctx.stat.lock() {
+            downloads = stat.downloads_by_books_ids(&ids)?;
+        }
+    }
+    facets.sort.sort(items, |item| {
+        let book = book(item);
+        SortKey {
+            title: &book.title,
+            added: &book.added,
+            serie: book
+                .serie
+                .as_ref()
+                .map(|serie| (serie.name.as_str(), serie.num)),
+            size: book.size,
+            downloads: downloads.get(&book.id).copied().unwrap_or_default(),
+        }
+    });
+    Ok(())
+}
+
+/// Keep the authors having books in the selected language,
+/// `ids` returns the ids of the first, middle and last names of the author
+fn filter_authors<T, F>(
+    ctx: &AppState,
+    feed: &mut Feed,
+    facets: &Facets,
+    authors: &mut Vec<T>,
+    ids: F,
+    locale: Locale,
+) -> anyhow::Result<()>
+where
+    F: Fn(&T) -> (u32, u32, u32),
+{
+    let keys = authors.iter().map(&ids).collect::<Vec<_>>();
+    // the list is kept as it is if the languages are not known
+    let Ok(catalog) = ctx.catalog() else {
+        return Ok(());
+    };
+    let langs = catalog.langs_by_authors_ids(&keys)?;
+    drop(catalog);
+    let langs = |author: &T| langs.get(&ids(author)).cloned().unwrap_or_default();
+    filter_by_lang(feed, facets, authors, langs, locale);
+    Ok(())
+}
+
+/// Keep the series having books in the selected language
+fn filter_series<T, F>(
+    ctx: &AppState,
+    feed: &mut Feed,
+    facets: &Facets,
+    series: &mut Vec<T>,
+    id: F,
+    locale: Locale,
+) -> anyhow::Result<()>
+where
+    F: Fn(&T) -> u32,
+{
+    let ids = series.iter().map(&id).collect::<Vec<_>>();
+    // the list is kept as it is if the languages are not known
+    let Ok(catalog) = ctx.catalog() else {
+        return Ok(());
+    };
+    let langs = catalog.langs_by_series_ids(&ids)?;
+    drop(catalog);
+    let langs = |serie: &T| langs.get(&id(serie)).cloned().unwrap_or_default();
+    filter_by_lang(feed, facets, series, langs, locale);
+    Ok(())
+}
+
 #[get("/opds/authors/favorits/days/{days}")]
-async fn opds_authors_favorits(ctx: AppCtx, args: web::Path<u8>,) -> impl Responder {
+async fn opds_authors_favorits(
+    ctx: AppCtx,
+    args: web::Path<u8>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
+) -> impl Responder {
     let days = args.into_inner();
-    info!("/opds/authors/favorits/days/{days}");
+    debug!("/opds/authors/favorits/days/{days}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
 
     let mut feed;
     let ids;
@@ -580,20 +1917,39 @@ async fn opds_authors_favorits(ctx: AppCtx, args: web::Path<u8>,) -> impl Respon
         ids = vec![];
     }
 
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Авторы за {days} дней");
-        feed.catalog("[Home]", "/opds");
-        let authors = api.authors_by_books_ids(ids).map_err(OpdsError)?;
-        for author in authors.into_iter() {
-            let title = format!("{author}");
-            let link = format!(
-                "/opds/author/id/{}/{}/{}",
-                author.first_name.id, author.middle_name.id, author.last_name.id
-            );
-            feed.catalog(title, link);
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr_args("authors.favorites", &[("days", days.to_string())]));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut authors = api.authors_by_books_ids(ids).map_err(OpdsError)?;
+        filter_authors(
+            &ctx,
+            &mut feed,
+            &facets,
+            &mut authors,
+            |author| {
+                (
+                    author.first_name.id,
+                    author.middle_name.id,
+                    author.last_name.id,
+                )
+            },
+            locale,
+        )
+        .map_err(OpdsError)?;
+        let authors = canonical_authors(&ctx, &authors, |author| {
+            (
+                author.first_name.id,
+                author.middle_name.id,
+                author.last_name.id,
+            )
+        })
+        .map_err(OpdsError)?;
+        for ((fid, mid, lid), name) in authors.into_iter() {
+            let link = format!("/opds/author/id/{fid}/{mid}/{lid}");
+            feed.catalog(name, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
     feed.format()
 }
@@ -602,29 +1958,37 @@ async fn opds_authors_favorits(ctx: AppCtx, args: web::Path<u8>,) -> impl Respon
 async fn opds_books_by_author_and_serie(
     ctx: AppCtx,
     args: web::Path<(u32, u32, u32, u32)>,
+    user: User,
+    facets: Facets,
+    locale: Locale,
 ) -> impl Responder {
     let (fid, mid, lid, sid) = args.into_inner();
-    info!("/opds/serie/books/id/{fid}/{mid}/{lid}/{sid}");
+    debug!("/opds/serie/books/id/{fid}/{mid}/{lid}/{sid}");
+    let facets = user_facets(&ctx, &user, facets).map_err(OpdsError)?;
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+    let (fid, mid, lid) = group[0];
+    let facets = facets.sort_by_default(Sort::Serie);
 
     let mut feed;
-    if let Ok(api) = ctx.api.lock() {
-        feed = Feed::new("Все книги по алфавиту");
-        feed.catalog("[Home]", "/opds");
-        let books = api
-            .books_by_author_ids_and_serie_id(fid, mid, lid, sid)
-            .map_err(OpdsError)?;
-        for book in books.iter() {
-            let title = format!("{book}");
-            let link = format!("/opds/book/id/{}", book.id);
-            feed.book(title, link);
+    if let Ok(api) = ctx.api() {
+        feed = Feed::new(locale.tr("books.author.serie"));
+        feed.catalog(locale.tr("home"), "/opds");
+        let mut ids = Vec::new();
+        for &(fid, mid, lid) in group.iter() {
+            let books = api
+                .books_by_author_ids_and_serie_id(fid, mid, lid, sid)
+                .map_err(OpdsError)?;
+            ids.extend(books.iter().map(|book| book.id));
         }
-        if books.is_empty() {
-            let title = format!("Вернуться к автору");
+        retain_unique(&mut ids, |id| *id);
+        let count = add_serie_books(&ctx, &mut feed, &facets, &ids, locale).map_err(OpdsError)?;
+        if count == 0 {
+            let title = String::from(locale.tr("author.back"));
             let link = format!("/opds/author/id/{}/{}/{}", fid, mid, lid);
             feed.catalog(title, link);
         }
     } else {
-        feed = Feed::new("Can't lock API");
+        feed = Feed::new(locale.tr("error.lock"));
     }
 
     feed.format()
@@ -633,38 +1997,696 @@ async fn opds_books_by_author_and_serie(
 #[get("/opds/book/id/{id}")]
 async fn opds_book_upload(ctx: AppCtx, args: web::Path<u32>) -> std::io::Result<NamedFile> {
     let id = args.into_inner();
-    info!("/opds/book/id/{id})");
+    debug!("/opds/book/id/{id})");
 
-    match books::extract_book(ctx.storage.clone(), id) {
+    match ctx.extract_book(id) {
         Ok(path) => {
             let stat = ctx.stat.lock().unwrap();
 
             if let Err(err) = stat.save(id) {
                 let msg = format!("{err}");
-                error!("{}", msg);
                 return Err(io::Error::new(io::ErrorKind::Other, msg));
             }
             match actix_files::NamedFile::open_async(path).await {
                 Ok(file) => {
                     info!("Uploading {} B", file.metadata().size());
+                    ctx.metrics.download(file.metadata().size());
                     Ok(file)
                 }
                 Err(err) => {
                     let msg = format!("{err}");
-                    error!("{}", msg);
                     return Err(io::Error::new(io::ErrorKind::Other, msg));
                 }
             }
         }
         Err(err) => {
             let msg = format!("{err}");
-            error!("{}", msg);
             return Err(io::Error::new(io::ErrorKind::Other, msg));
         }
     }
 }
 
+#[get("/opds/book/info/{id}")]
+async fn opds_book_info(ctx: AppCtx, args: web::Path<u32>, locale: Locale) -> impl Responder {
+    let id = args.into_inner();
+    debug!("/opds/book/info/{id}");
+
+    let path = ctx.extract_book(id).map_err(|e| OpdsError(e.into()))?;
+    let desc = fb2::read_description(&path).map_err(OpdsError)?;
+
+    let mut feed = Feed::new(locale.tr("book.info"));
+    feed.catalog(locale.tr("home"), "/opds");
+
+    let mut entry = Entry::book(desc.title.clone(), format!("/opds/book/id/{id}"));
+    entry.authors = desc.authors.iter().map(|a| format!("{a}")).collect();
+    entry.contributors = desc.translators.iter().map(|t| format!("{t}")).collect();
+
+    let mut content = vec![desc.annotation.clone()];
+    for sequence in desc.sequences.iter() {
+        content.push(locale.tr_args("book.serie", &[("serie", sequence.to_string())]));
+    }
+    if !desc.translators.is_empty() {
+        let names = entry.contributors.join(", ");
+        content.push(locale.tr_args("book.translators", &[("names", names)]));
+    }
+    if !desc.publisher.is_empty() {
+        let publisher = format!("{} {} {}", desc.publisher, desc.city, desc.year);
+        content.push(locale.tr_args("book.publisher", &[("publisher", publisher)]));
+    }
+    if !desc.isbn.is_empty() {
+        content.push(locale.tr_args("book.isbn", &[("isbn", desc.isbn.clone())]));
+    }
+    entry.content = Some(content.join("\n").trim().to_string());
+
+    if !desc.lang.is_empty() {
+        entry.dc.push(("language", desc.lang.clone()));
+    }
+    let issued = if desc.year.is_empty() {
+        &desc.date
+    } else {
+        &desc.year
+    };
+    if !issued.is_empty() {
+        entry.dc.push(("issued", issued.clone()));
+    }
+    if !desc.publisher.is_empty() {
+        entry.dc.push(("publisher", desc.publisher.clone()));
+    }
+    if !desc.isbn.is_empty() {
+        let isbn = format!("urn:isbn:{}", desc.isbn);
+        entry.dc.push(("identifier", isbn));
+    }
+
+    if let Ok(api) = ctx.api() {
+        let authors = api.authors_by_books_ids(vec![id]).map_err(OpdsError)?;
+        for author in authors.into_iter() {
+            let title = locale.tr_args("book.related.author", &[("author", author.to_string())]);
+            let link = format!(
+                "/opds/author/id/{}/{}/{}",
+                author.first_name.id, author.middle_name.id, author.last_name.id
+            );
+            entry.link("related", &title, &link, CATALOG_TYPE);
+        }
+    }
+    if let Ok(catalog) = ctx.catalog() {
+        if let Some(serie) = catalog.serie_by_book_id(id).map_err(OpdsError)? {
+            let title = locale.tr_args("book.related.serie", &[("serie", serie.to_string())]);
+            let link = format!("/opds/books/serie/id/{}", serie.id);
+            entry.link("related", &title, &link, CATALOG_TYPE);
+        }
+        let genres = catalog.genres_by_book_id(id).map_err(OpdsError)?;
+        for genre in genres.into_iter() {
+            let title = locale.tr_args("book.related.genre", &[("genre", genre.name.clone())]);
+            let link = format!("/opds/genre/id/{}", genre.id);
+            entry.link("related", &title, &link, CATALOG_TYPE);
+            entry.categories.push(genre.name);
+        }
+    }
+    if entry.categories.is_empty() {
+        entry.categories = desc.genres.clone();
+    }
+    let read = format!("/read/{id}");
+    entry.link("alternate", locale.tr("book.read"), &read, "text/html");
+    let send = format!("/opds/book/id/{id}/send");
+    entry.link("related", locale.tr("book.send"), &send, CATALOG_TYPE);
+
+    feed.push(entry);
+    feed.format()
+}
+
+#[get("/opds/book/id/{id}/send")]
+async fn opds_book_send(
+    ctx: AppCtx,
+    args: web::Path<u32>,
+    user: User,
+    locale: Locale,
+) -> impl Responder {
+    let id = args.into_inner();
+    debug!("/opds/book/id/{id}/send");
+
+    let mut feed = Feed::new(locale.tr("send.title"));
+    feed.catalog(locale.tr("home"), "/opds");
+    let info = format!("/opds/book/info/{id}");
+    // the book is sent by the form only, the clients may prefetch the links
+    match send_address(&ctx, &user, locale).map_err(OpdsError)? {
+        Ok(address) => {
+            let message = locale.tr_args("send.confirm", &[("address", address)]);
+            let entry = feed.push(Entry::catalog(message, info));
+            let send = format!("/read/{id}/send");
+            entry.link("alternate", locale.tr("send.button"), &send, "text/html");
+        }
+        Err(message) => {
+            let entry = feed.push(Entry::catalog(String::from(message), info));
+            let title = locale.tr("settings.title");
+            entry.link("related", title, "/settings", "text/html");
+        }
+    }
+    feed.format()
+}
+
+/// Returns the e-mail address of the user or the message why the book can not be sent
+fn send_address(
+    ctx: &AppState,
+    user: &User,
+    locale: Locale,
+) -> anyhow::Result<Result<String, &'static str>> {
+    if ctx.outbox.get().is_none() {
+        return Ok(Err(locale.tr("send.disabled")));
+    }
+    if user.is_anonymous() {
+        return Ok(Err(locale.tr("send.anonymous")));
+    }
+    let stat = ctx.stat.lock().map_err(|e| anyhow::anyhow!("{e}"))?;
+    match stat.load_setting(user.name(), SETTING_EMAIL)? {
+        Some(address) => Ok(Ok(address)),
+        None => Ok(Err(locale.tr("send.no_address"))),
+    }
+}
+
+/// Queue the book to the e-mail address of the user, returns the message about the result.
+/// The book already queued or sent recently to the address is not queued again.
+fn queue_book(ctx: &AppState, id: u32, user: &User, locale: Locale) -> anyhow::Result<String> {
+    let address = match send_address(ctx, user, locale)? {
+        Ok(address) => address,
+        Err(message) => return Ok(String::from(message)),
+    };
+    let stat = ctx.stat.lock().map_err(|e| anyhow::anyhow!("{e}"))?;
+    if stat.has_recent_mail(id, &address, RECENT_MAIL_MINUTES)? {
+        return Ok(locale.tr_args("send.duplicate", &[("address", address)]));
+    }
+    stat.queue_mail(id, &address)?;
+    if let Some(outbox) = ctx.outbox.get() {
+        outbox.notify();
+    }
+    Ok(locale.tr_args("send.queued", &[("address", address)]))
+}
+
+#[get("/read/{id}")]
+async fn read_book(ctx: AppCtx, args: web::Path<u32>, urls: Urls) -> Result<HttpResponse> {
+    let id = args.into_inner();
+    debug!("/read/{id}");
+
+    let page = match ctx.stat.lock() {
+        Ok(stat) => stat.load_position(id).map_err(OpdsError)?.unwrap_or(1),
+        Err(_) => 1,
+    };
+    let location = urls.url(&format!("/read/{id}/page/{page}"));
+    Ok(HttpResponse::Found()
+        .append_header((header::LOCATION, location))
+        .finish())
+}
+
+/// Returns the book rendered for the reader, the recently read books are not parsed again
+fn open_book(ctx: &AppState, id: u32) -> anyhow::Result<Arc<reader::Book>> {
+    ctx.opened
+        .get_or_load(id, || reader::load(&ctx.extract_book(id)?))
+}
+
+#[get("/read/{id}/toc")]
+async fn read_book_toc(ctx: AppCtx, args: web::Path<u32>, urls: Urls) -> Result<HttpResponse> {
+    let id = args.into_inner();
+    debug!("/read/{id}/toc");
+
+    let book = open_book(&ctx, id).map_err(OpdsError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(reader::format_toc(&book, id, &urls)))
+}
+
+#[get("/read/{id}/page/{page}")]
+async fn read_book_page(
+    ctx: AppCtx,
+    args: web::Path<(u32, u32)>,
+    urls: Urls,
+) -> Result<HttpResponse> {
+    let (id, page) = args.into_inner();
+    debug!("/read/{id}/page/{page}");
+
+    let book = open_book(&ctx, id).map_err(OpdsError)?;
+    let page = page.clamp(1, book.pages.len() as u32);
+    if let Ok(stat) = ctx.stat.lock() {
+        let _ = stat
+            .save_position(id, page)
+            .inspect_err(|err| warn!("The position in the book {id} is not saved: {err}"));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(reader::format_page(&book, id, page as usize, &urls)))
+}
+
+#[get("/read/{id}/send")]
+async fn read_book_send_confirm(
+    ctx: AppCtx,
+    args: web::Path<u32>,
+    user: User,
+    locale: Locale,
+    urls: Urls,
+) -> Result<HttpResponse> {
+    let id = args.into_inner();
+    debug!("/read/{id}/send");
+
+    let back = urls.url(&format!("/read/{id}"));
+    let page = match send_address(&ctx, &user, locale).map_err(OpdsError)? {
+        Ok(address) => {
+            let message = locale.tr_args("send.confirm", &[("address", address)]);
+            let action = urls.url(&format!("/read/{id}/send"));
+            let button = locale.tr("send.button");
+            let token = &ctx.csrf_token;
+            let title = locale.tr("send.title");
+            pages::format_confirm(title, &message, &action, button, token, &back)
+        }
+        Err(message) => pages::format_message(locale.tr("send.title"), message, &back),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page))
+}
+
+#[post("/read/{id}/send")]
+async fn read_book_send(
+    ctx: AppCtx,
+    args: web::Path<u32>,
+    form: web::Form<HashMap<String, String>>,
+    user: User,
+    locale: Locale,
+    urls: Urls,
+) -> Result<HttpResponse> {
+    let id = args.into_inner();
+    debug!("/read/{id}/send");
+    if user.is_anonymous() || !has_token(&form, &ctx.csrf_token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let message = queue_book(&ctx, id, &user, locale).map_err(OpdsError)?;
+    let back = urls.url(&format!("/read/{id}"));
+    let page = pages::format_message(locale.tr("send.title"), &message, &back);
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page))
+}
+
+#[get("/settings")]
+async fn settings(ctx: AppCtx, user: User, locale: Locale, urls: Urls) -> Result<HttpResponse> {
+    debug!("/settings");
+
+    settings_page(&ctx, &user, locale, &urls, None)
+}
+
+#[post("/settings")]
+async fn settings_save(
+    ctx: AppCtx,
+    form: web::Form<HashMap<String, String>>,
+    user: User,
+    locale: Locale,
+    urls: Urls,
+) -> Result<HttpResponse> {
+    debug!("/settings");
+    if !has_token(&form, &ctx.csrf_token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let email = form.get(SETTING_EMAIL).map_or("", |e| e.trim());
+    if !email.is_empty() && !mail::is_valid_address(email) {
+        let error = locale.tr_args("settings.email.invalid", &[("address", email.to_string())]);
+        return settings_page(&ctx, &user, locale, &urls, Some(&error));
+    }
+    let lang = match form.get(SETTING_LANG).map(|lang| Lang::from(lang.as_str())) {
+        Some(Lang::Only(lang)) => lang,
+        _ => String::new(),
+    };
+    if let Ok(stat) = ctx.stat.lock() {
+        // the anonymous users share the settings, so they may not set the address
+        if !user.is_anonymous() {
+            stat.save_setting(user.name(), SETTING_EMAIL, email)
+                .map_err(OpdsError)?;
+        }
+        stat.save_setting(user.name(), SETTING_LANG, &lang)
+            .map_err(OpdsError)?;
+    }
+    Ok(HttpResponse::SeeOther()
+        .append_header((header::LOCATION, urls.url("/settings")))
+        .finish())
+}
+
+fn settings_page(
+    ctx: &AppState,
+    user: &User,
+    locale: Locale,
+    urls: &Urls,
+    error: Option<&str>,
+) -> Result<HttpResponse> {
+    let (email, lang, mails) = match ctx.stat.lock() {
+        Ok(stat) => {
+            let email = stat
+                .load_setting(user.name(), SETTING_EMAIL)
+                .map_err(OpdsError)?
+                .unwrap_or_default();
+            let lang = stat
+                .load_setting(user.name(), SETTING_LANG)
+                .map_err(OpdsError)?
+                .unwrap_or_default();
+            let mails = match user.is_anonymous() {
+                true => vec![],
+                false => stat.mails(&email, RECENT_MAILS).map_err(OpdsError)?,
+            };
+            (email, lang, mails)
+        }
+        Err(_) => (String::new(), String::new(), vec![]),
+    };
+    let langs = match ctx.catalog() {
+        Ok(catalog) => catalog.langs(None).map_err(OpdsError)?,
+        Err(_) => vec![],
+    };
+    let values = pages::Settings {
+        email: Some(email.as_str()).filter(|_| !user.is_anonymous()),
+        lang: &lang,
+        langs: &langs,
+        mails: &mails,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(pages::format_settings(
+            locale,
+            &values,
+            error,
+            &ctx.csrf_token,
+            urls,
+        )))
+}
+
+#[get("/admin/authors")]
+async fn admin_authors(
+    ctx: AppCtx,
+    user: User,
+    locale: Locale,
+    urls: Urls,
+) -> Result<HttpResponse> {
+    debug!("/admin/authors");
+
+    admin_authors_page(&ctx, &user, locale, &urls, None)
+}
+
+#[post("/admin/authors")]
+async fn admin_authors_save(
+    ctx: AppCtx,
+    form: web::Form<HashMap<String, String>>,
+    user: User,
+    locale: Locale,
+    urls: Urls,
+) -> Result<HttpResponse> {
+    debug!("/admin/authors");
+    if !ctx.is_admin(&user) || !has_token(&form, &ctx.csrf_token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let ids = |name: &str| form.get(name).and_then(|ids| aliases::parse_ids(ids));
+    let result = match (form.get("action").map(String::as_str), ids("alias")) {
+        (Some("merge"), Some(alias)) => match ids("canonical") {
+            Some(canonical) => ctx
+                .aliases
+                .lock()
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .and_then(|aliases| aliases.merge(alias, canonical)),
+            None => Err(anyhow::anyhow!("{}", locale.tr("admin.ids.invalid"))),
+        },
+        (Some("split"), Some(alias)) => ctx
+            .aliases
+            .lock()
+            .map_err(|e| anyhow::anyhow!("{e}"))
+            .and_then(|aliases| aliases.split(alias)),
+        _ => Err(anyhow::anyhow!("{}", locale.tr("admin.ids.invalid"))),
+    };
+    if let Err(err) = result {
+        warn!("{user:?} failed to change the aliases: {err}");
+        return admin_authors_page(&ctx, &user, locale, &urls, Some(&err.to_string()));
+    }
+    info!("{user:?} changed the aliases: {:?}", form.0);
+    Ok(HttpResponse::SeeOther()
+        .append_header((header::LOCATION, urls.url("/admin/authors")))
+        .finish())
+}
+
+/// The page of the merged authors and the suggested duplicates
+fn admin_authors_page(
+    ctx: &AppState,
+    user: &User,
+    locale: Locale,
+    urls: &Urls,
+    error: Option<&str>,
+) -> Result<HttpResponse> {
+    if !ctx.is_admin(user) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let merged = match ctx.aliases.lock() {
+        Ok(aliases) => aliases.all().map_err(OpdsError)?,
+        Err(_) => vec![],
+    };
+    let mut aliases = Vec::new();
+    let mut suggestions = Vec::new();
+    if let Ok(catalog) = ctx.catalog() {
+        let author = |(fid, mid, lid): AuthorIds| -> anyhow::Result<catalog::Author> {
+            let author = catalog.author_by_ids(fid, mid, lid)?;
+            Ok(author.unwrap_or_else(|| catalog::Author {
+                ids: (fid, mid, lid),
+                first: String::new(),
+                middle: String::new(),
+                last: format!("{fid}/{mid}/{lid}"),
+            }))
+        };
+        for &(alias, canonical) in merged.iter() {
+            let alias = author(alias).map_err(OpdsError)?;
+            aliases.push((alias, author(canonical).map_err(OpdsError)?));
+        }
+
+        let authors = catalog.authors_with_namesakes().map_err(OpdsError)?;
+        let is_merged = |ids: AuthorIds| merged.iter().any(|(alias, _)| *alias == ids);
+        let by_ids = authors
+            .iter()
+            .map(|author| (author.ids, author))
+            .collect::<HashMap<_, _>>();
+        for (alias, canonical) in aliases::suggest(&authors).into_iter() {
+            if is_merged(alias) || is_merged(canonical) {
+                continue;
+            }
+            suggestions.push((by_ids[&alias].clone(), by_ids[&canonical].clone()));
+            if suggestions.len() >= MAX_SUGGESTIONS {
+                break;
+            }
+        }
+    }
+    let token = &ctx.csrf_token;
+    let page = pages::format_aliases(locale, &aliases, &suggestions, error, token, urls);
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page))
+}
+
+#[get("/feeds/genre/{gid}.atom")]
+async fn feeds_genre(ctx: AppCtx, args: web::Path<u32>, locale: Locale) -> impl Responder {
+    let gid = args.into_inner();
+    debug!("/feeds/genre/{gid}.atom");
+
+    // the feed readers would cache the empty feed
+    let catalog = ctx
+        .catalog()
+        .map_err(|_| ErrorServiceUnavailable(locale.tr("error.lock")))?;
+    let genre = catalog.genre_by_id(gid).map_err(OpdsError)?;
+    let books = catalog
+        .latest_books_by_genre_id(gid, ctx.feed_size)
+        .map_err(OpdsError)?;
+    drop(catalog);
+    let genre = genre.map(|genre| genre.name).unwrap_or_default();
+    let title = locale.tr_args("feeds.genre", &[("genre", genre)]);
+    let id = format!("urn:fb2s:feed:genre:{gid}");
+    let href = format!("/feeds/genre/{gid}.atom");
+    subscription(title, id, href, books, locale).atom()
+}
+
+#[get("/feeds/author/{fid}/{mid}/{lid}.atom")]
+async fn feeds_author(
+    ctx: AppCtx,
+    args: web::Path<(u32, u32, u32)>,
+    locale: Locale,
+) -> impl Responder {
+    let (fid, mid, lid) = args.into_inner();
+    debug!("/feeds/author/{fid}/{mid}/{lid}.atom");
+    let group = author_group(&ctx, (fid, mid, lid)).map_err(OpdsError)?;
+
+    let catalog = ctx
+        .catalog()
+        .map_err(|_| ErrorServiceUnavailable(locale.tr("error.lock")))?;
+    let canonical = group[0];
+    let author = catalog
+        .author_name_by_ids(canonical.0, canonical.1, canonical.2)
+        .map_err(OpdsError)?;
+    let mut books = Vec::new();
+    for &(fid, mid, lid) in group.iter() {
+        let latest = catalog
+            .latest_books_by_author_ids(fid, mid, lid, ctx.feed_size)
+            .map_err(OpdsError)?;
+        books.extend(latest);
+    }
+    drop(catalog);
+    retain_unique(&mut books, |book| book.id);
+    books.sort_by(|a, b| (&b.added, b.id).cmp(&(&a.added, a.id)));
+    books.truncate(ctx.feed_size as usize);
+    let author = author.unwrap_or_default();
+    let title = locale.tr_args("feeds.author", &[("author", author)]);
+    let id = format!("urn:fb2s:feed:author:{fid}:{mid}:{lid}");
+    let href = format!("/feeds/author/{fid}/{mid}/{lid}.atom");
+    subscription(title, id, href, books, locale).atom()
+}
+
+/// Build the feed of the latest books for the feed readers.
+/// The ids of the entries do not depend on the feed, so the book is not shown twice.
+fn subscription(
+    title: String,
+    id: String,
+    href: String,
+    books: Vec<catalog::Book>,
+    locale: Locale,
+) -> Feed {
+    let mut feed = Feed::new(title.clone());
+    feed.id = Some(id);
+    feed.updated = books.first().map(|book| added_time(&book.added));
+    feed.link("self", &title, &href, ATOM_TYPE);
+    for book in books.into_iter() {
+        let read = format!("/read/{}", book.id);
+        let mut entry = Entry::book(book.title.clone(), read);
+        entry.id = format!("urn:fb2s:book:{}", book.id);
+        entry.htype = String::from("text/html");
+        entry.updated = Some(added_time(&book.added));
+        if let Some(serie) = &book.serie {
+            let content = locale.tr_args("book.serie", &[("serie", serie.to_string())]);
+            entry.content = Some(content);
+        }
+        if !book.lang.is_empty() {
+            entry.dc.push(("language", book.lang.clone()));
+        }
+        entry.authors = book.authors;
+        let info = format!("/opds/book/info/{}", book.id);
+        entry.link("alternate", locale.tr("book.info.link"), &info, ENTRY_TYPE);
+        let download = format!("/opds/book/id/{}", book.id);
+        entry.link("enclosure", "fb2", &download, "application/fb2+zip");
+        feed.push(entry);
+    }
+    feed
+}
+
+/// Index the names of the catalog for the search in the other script, the search
+/// without the index finds only the names in the script of the query
+fn index_names(catalog: &mut CatalogApi) {
+    let _ = catalog
+        .index_names()
+        .inspect_err(|err| warn!("The names are not indexed for the transliteration: {err}"));
+}
+
+/// Returns true if the form is posted with the token from the pages of the server
+fn has_token(form: &HashMap<String, String>, token: &str) -> bool {
+    form.get("token").is_some_and(|value| value == token)
+}
+
+/// Returns the random token of 16 bytes in hex
+fn random_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Returns the time in RFC 3339 of the date `YYYY-MM-DD` when the book was added
+fn added_time(added: &str) -> String {
+    match chrono::NaiveDate::parse_from_str(added, "%Y-%m-%d") {
+        Ok(date) => format!("{date}T00:00:00Z"),
+        Err(_) => String::from("1970-01-01T00:00:00Z"),
+    }
+}
+
+#[get("/health")]
+async fn service_health() -> impl Responder {
+    debug!("/health");
+    HttpResponse::Ok().content_type("text/plain").body("OK\n")
+}
+
+#[get("/ready")]
+async fn service_ready(ctx: AppCtx) -> impl Responder {
+    debug!("/ready");
+
+    let catalog = match ctx.catalog() {
+        Ok(catalog) => catalog.check(),
+        Err(err) => Err(anyhow::anyhow!("{err}")),
+    };
+    let library = std::fs::read_dir(&ctx.storage).map_err(anyhow::Error::from);
+    let statistic = match ctx.stat.lock() {
+        Ok(stat) => stat.is_readonly().and_then(|readonly| match readonly {
+            true => Err(anyhow::anyhow!("read only")),
+            false => Ok(()),
+        }),
+        Err(err) => Err(anyhow::anyhow!("{err}")),
+    };
+
+    let checks = [
+        ("catalog", catalog.err()),
+        ("library", library.err()),
+        ("statistic", statistic.err()),
+    ];
+    let mut body = String::new();
+    for (name, err) in checks.iter() {
+        match err {
+            Some(err) => body.push_str(&format!("{name}: {err}\n")),
+            None => body.push_str(&format!("{name}: OK\n")),
+        }
+    }
+    if checks.iter().all(|(_, err)| err.is_none()) {
+        HttpResponse::Ok().content_type("text/plain").body(body)
+    } else {
+        warn!("Not ready: {}", body.trim().replace('\n', ", "));
+        HttpResponse::ServiceUnavailable()
+            .content_type("text/plain")
+            .body(body)
+    }
+}
+
+#[get("/metrics")]
+async fn service_metrics(ctx: AppCtx) -> impl Responder {
+    debug!("/metrics");
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(ctx.metrics.render())
+}
+
 // /*********************************************************************************/
+fn import(args: &[String]) -> anyhow::Result<()> {
+    let Some(index) = args.first().map(PathBuf::from) else {
+        anyhow::bail!("Usage: opds_server import <index.inpx> [books.db]");
+    };
+    let database = match args.get(1) {
+        Some(database) => database.clone(),
+        None => index.with_file_name("books.db").display().to_string(),
+    };
+    info!("Import {} into {database}", index.display());
+
+    let summary = import::import_inpx(&index, &database)?;
+    info!("Imported: {summary}");
+    Ok(())
+}
+
+fn update(args: &[String]) -> anyhow::Result<()> {
+    let library = match args.first() {
+        Some(library) => PathBuf::from(library),
+        None => PathBuf::from(get_env("FB2S_LIBRARY", DEFAULT_LIBRARY)),
+    };
+    let database = match args.get(1) {
+        Some(database) => database.clone(),
+        None => library.join("books.db").display().to_string(),
+    };
+    info!("Update {database} from {}", library.display());
+
+    let summary = import::update_library(&library, &database)?;
+    info!("Updated: {summary}");
+    Ok(())
+}
+
 fn get_env<T: Into<String> + Display>(name: T, default: T) -> String {
     let name = name.into();
     let default = default.into();
@@ -676,3 +2698,71 @@ fn get_env<T: Into<String> + Display>(name: T, default: T) -> String {
         })
         .expect(&format!("Can't configure {}", name))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    /// Returns the state over the empty catalog in the temporary directory
+    fn state(name: &str) -> AppCtx {
+        let dir = std::env::temp_dir().join(format!("opds-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let database = dir.join("books.db");
+        let conn = rusqlite::Connection::open(&database).unwrap();
+        conn.execute_batch(import::SCHEMA).unwrap();
+        let stat = StatisticApi::try_from(":memory:").unwrap();
+        let aliases = AliasApi::try_from(":memory:").unwrap();
+        let database = database.to_string_lossy().into_owned();
+        web::Data::new(AppState::new(database, stat, aliases, dir).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_forms_need_token() {
+        let ctx = state("forms");
+        let token = ctx.csrf_token.clone();
+        let app = test::init_service(
+            App::new()
+                .app_data(ctx.clone())
+                .app_data(TrustedProxies::new("127.0.0.1"))
+                .service(read_book_send)
+                .service(settings_save),
+        )
+        .await;
+        let post = |uri: &str, form: &[(&str, &str)]| {
+            let form = form
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>();
+            test::TestRequest::post()
+                .uri(uri)
+                .peer_addr("127.0.0.1:1234".parse().unwrap())
+                .set_form(form)
+        };
+
+        // the form of another site has no token
+        let req = post("/settings", &[("email", "evil@example.com")]).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+        let req = post("/read/1/send", &[]).insert_header(("X-Remote-User", "reader"));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+
+        // the anonymous user may not send the books even with the token
+        let req = post("/read/1/send", &[("token", &token)]).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+        let form = [("token", token.as_str()), ("email", "evil@example.com")];
+        let res = test::call_service(&app, post("/settings", &form).to_request()).await;
+        assert_eq!(StatusCode::SEE_OTHER, res.status());
+        let email = ctx.stat.lock().unwrap().load_setting("", SETTING_EMAIL);
+        assert_eq!(None, email.unwrap());
+
+        let req = post("/read/1/send", &[("token", &token)]);
+        let req = req.insert_header(("X-Remote-User", "reader"));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let _ = std::fs::remove_dir_all(&ctx.storage);
+    }
+}
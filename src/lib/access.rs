@@ -0,0 +1,344 @@
+//! Access log middleware writing one line per request.
+//!
+//! Every request gets an id, taken from the `X-Request-Id` header or generated,
+//! which is returned in the response headers and prefixes the error logs of the request.
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use futures::future::LocalBoxFuture;
+use log::{error, info, warn};
+
+use crate::user;
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::future::{ready, Ready};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_REQUEST_ID_LEN: usize = 64;
+const ROTATED_FILES: usize = 5;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The id of the request, available in the request extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+impl RequestId {
+    /// Use the id provided by the proxy or generate the new one
+    fn from_request(req: &ServiceRequest) -> Self {
+        let provided = req
+            .headers()
+            .get(&REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+            .filter(|id| {
+                id.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            });
+        match provided {
+            Some(id) => RequestId(String::from(id)),
+            None => RequestId::generate(),
+        }
+    }
+
+    fn generate() -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+        RequestId(format!("{started:x}-{counter:06x}"))
+    }
+}
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+impl From<&str> for Format {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "json" => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// The log file which is rotated to `<name>.1` ... `<name>.5` when it exceeds the limit
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    limit: u64,
+    file: File,
+    size: u64,
+}
+impl RotatingFile {
+    pub fn open(path: PathBuf, limit: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            limit,
+            file,
+            size,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..ROTATED_FILES).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.limit > 0 && self.size >= self.limit {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// The record of the access log
+struct Record<'a> {
+    id: &'a RequestId,
+    peer: &'a str,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    bytes: Option<u64>,
+    latency: f64,
+    user_agent: &'a str,
+}
+impl Record<'_> {
+    fn format(&self, format: Format) -> String {
+        match format {
+            Format::Text => format!(
+                "{} {} \"{} {}\" {} {} {:.3}ms \"{}\"",
+                self.id,
+                self.peer,
+                escape(self.method),
+                escape(self.path),
+                self.status,
+                self.bytes.map_or(String::from("-"), |b| b.to_string()),
+                self.latency * 1000.0,
+                escape(self.user_agent)
+            ),
+            Format::Json => serde_json::json!({
+                "id": self.id.0,
+                "peer": self.peer,
+                "method": self.method,
+                "path": self.path,
+                "status": self.status,
+                "bytes": self.bytes,
+                "latency": self.latency,
+                "user_agent": self.user_agent,
+            })
+            .to_string(),
+        }
+    }
+}
+
+/// Escape the value written in quotes like the combined log format tools expect:
+/// `"` and `\` with the backslash, the control chars as `\xHH`
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug)]
+struct Sink {
+    format: Format,
+    file: Option<Mutex<RotatingFile>>,
+}
+impl Sink {
+    fn write(&self, record: &Record) {
+        let line = record.format(self.format);
+        match &self.file {
+            Some(file) => {
+                if let Ok(mut file) = file.lock() {
+                    if let Err(err) = file.write_line(&line) {
+                        warn!("Access log: {err}");
+                    }
+                }
+            }
+            None => info!(target: "access", "{line}"),
+        }
+    }
+}
+
+/// The access log middleware, the log is written by `log` or into the rotating file
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    sink: Arc<Sink>,
+}
+impl AccessLog {
+    pub fn new(format: Format, file: Option<RotatingFile>) -> Self {
+        let file = file.map(Mutex::new);
+        AccessLog {
+            sink: Arc::new(Sink { format, file }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AccessLogMiddleware {
+            service: Rc::new(service),
+            sink: self.sink.clone(),
+        }))
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: Rc<S>,
+    sink: Arc<Sink>,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLogMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started = Instant::now();
+        let id = RequestId::from_request(&req);
+        req.extensions_mut().insert(id.clone());
+
+        // the forwarded client address is trusted only from the proxies
+        let peer = match user::from_trusted_proxy(req.request()) {
+            true => req.connection_info().realip_remote_addr().map(String::from),
+            false => req.peer_addr().map(|addr| addr.ip().to_string()),
+        }
+        .unwrap_or_else(|| String::from("-"));
+        let method = req.method().to_string();
+        let path = req
+            .uri()
+            .path_and_query()
+            .map_or(String::from("/"), |p| p.to_string());
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("-")
+            .to_string();
+
+        let service = self.service.clone();
+        let sink = self.sink.clone();
+        Box::pin(async move {
+            let mut res = match service.call(req).await {
+                Ok(res) => res,
+                Err(err) => {
+                    error!("[{id}] {method} {path}: {err}");
+                    return Err(err);
+                }
+            };
+            if let Some(err) = res.response().error() {
+                error!("[{id}] {method} {path}: {err}");
+            }
+            if let Ok(value) = HeaderValue::from_str(&id.0) {
+                res.headers_mut().insert(REQUEST_ID, value);
+            }
+
+            let bytes = match res.response().body().size() {
+                BodySize::Sized(size) => Some(size),
+                _ => None,
+            };
+            sink.write(&Record {
+                id: &id,
+                peer: &peer,
+                method: &method,
+                path: &path,
+                status: res.status().as_u16(),
+                bytes,
+                latency: started.elapsed().as_secs_f64(),
+                user_agent: &user_agent,
+            });
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let id = RequestId(String::from("abc-000001"));
+        let record = Record {
+            id: &id,
+            peer: "127.0.0.1",
+            method: "GET",
+            path: "/opds?x=\"1\"",
+            status: 200,
+            bytes: Some(512),
+            latency: 0.0125,
+            user_agent: "FBReader\\\" 404 \n",
+        };
+        assert_eq!(
+            r#"abc-000001 127.0.0.1 "GET /opds?x=\"1\"" 200 512 12.500ms "FBReader\\\" 404 \x0A""#,
+            record.format(Format::Text)
+        );
+        let json: serde_json::Value = serde_json::from_str(&record.format(Format::Json)).unwrap();
+        assert_eq!("/opds?x=\"1\"", json["path"]);
+        assert_eq!(512, json["bytes"]);
+    }
+}
@@ -0,0 +1,270 @@
+//! The aliases of the authors kept in the local database beside the read-only catalog.
+//!
+//! The author is addressed by the ids of the first, middle and last names, so the person
+//! written differently like `Стругацкий А.` and `Стругацкий Аркадий Натанович` is listed
+//! several times. The alias maps such ids to the canonical author and the feeds of the
+//! canonical author include the books of all its aliases:
+//!  * `aliases(first_name_id, middle_name_id, last_name_id,
+//!    canonical_first_name_id, canonical_middle_name_id, canonical_last_name_id)`
+use log::{debug, error};
+use rusqlite::Connection;
+
+use crate::catalog::Author;
+
+use std::convert::TryFrom;
+
+/// The ids of the first, middle and last names of the author
+pub type AuthorIds = (u32, u32, u32);
+
+#[derive(Debug)]
+pub struct AliasApi {
+    conn: Connection,
+}
+impl AliasApi {
+    /// Create AliasApi instance
+    pub fn new(conn: Connection) -> Self {
+        AliasApi { conn }
+    }
+
+    /// Make the author the alias of the canonical one, the aliases of the author
+    /// are moved to the canonical author too
+    pub fn merge(&self, alias: AuthorIds, canonical: AuthorIds) -> anyhow::Result<()> {
+        let canonical = self.canonical(canonical)?;
+        if alias == canonical {
+            anyhow::bail!("The author {alias:?} can not be the alias of itself");
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        let sql = r#"
+            UPDATE aliases
+            SET canonical_first_name_id = $1,
+                canonical_middle_name_id = $2,
+                canonical_last_name_id = $3
+            WHERE canonical_first_name_id = $4
+              AND canonical_middle_name_id = $5
+              AND canonical_last_name_id = $6;
+        "#;
+        tx.execute(
+            sql,
+            [
+                canonical.0,
+                canonical.1,
+                canonical.2,
+                alias.0,
+                alias.1,
+                alias.2,
+            ],
+        )?;
+        let sql = "INSERT INTO aliases VALUES($1, $2, $3, $4, $5, $6);";
+        tx.execute(
+            sql,
+            [
+                alias.0,
+                alias.1,
+                alias.2,
+                canonical.0,
+                canonical.1,
+                canonical.2,
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Make the alias the separate author again
+    pub fn split(&self, alias: AuthorIds) -> anyhow::Result<()> {
+        let sql = r#"
+            DELETE FROM aliases
+            WHERE first_name_id = $1 AND middle_name_id = $2 AND last_name_id = $3;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let _ = statement.execute([alias.0, alias.1, alias.2])?;
+        Ok(())
+    }
+
+    /// Returns the canonical author of the ids, the same ids if the author is not an alias
+    pub fn canonical(&self, ids: AuthorIds) -> anyhow::Result<AuthorIds> {
+        let sql = r#"
+            SELECT canonical_first_name_id, canonical_middle_name_id, canonical_last_name_id
+            FROM aliases
+            WHERE first_name_id = $1 AND middle_name_id = $2 AND last_name_id = $3;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let mut rows = statement.query_map([ids.0, ids.1, ids.2], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        Ok(rows.next().transpose()?.unwrap_or(ids))
+    }
+
+    /// Returns the canonical author of the ids followed by all its aliases
+    pub fn group(&self, ids: AuthorIds) -> anyhow::Result<Vec<AuthorIds>> {
+        let canonical = self.canonical(ids)?;
+        let sql = r#"
+            SELECT first_name_id, middle_name_id, last_name_id
+            FROM aliases
+            WHERE canonical_first_name_id = $1
+              AND canonical_middle_name_id = $2
+              AND canonical_last_name_id = $3
+            ORDER BY last_name_id, first_name_id, middle_name_id;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([canonical.0, canonical.1, canonical.2], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+
+        let mut group = vec![canonical];
+        for alias in rows {
+            group.push(alias?);
+        }
+        Ok(group)
+    }
+
+    /// Returns all aliases with their canonical authors
+    pub fn all(&self) -> anyhow::Result<Vec<(AuthorIds, AuthorIds)>> {
+        let sql = r#"
+            SELECT first_name_id, middle_name_id, last_name_id,
+                canonical_first_name_id, canonical_middle_name_id, canonical_last_name_id
+            FROM aliases
+            ORDER BY canonical_last_name_id, canonical_first_name_id, canonical_middle_name_id;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([], |row| {
+            let alias = (row.get(0)?, row.get(1)?, row.get(2)?);
+            let canonical = (row.get(3)?, row.get(4)?, row.get(5)?);
+            Ok((alias, canonical))
+        })?;
+
+        let mut aliases = Vec::new();
+        for alias in rows {
+            aliases.push(alias?);
+        }
+        Ok(aliases)
+    }
+}
+impl TryFrom<&str> for AliasApi {
+    type Error = anyhow::Error;
+
+    fn try_from(database: &str) -> anyhow::Result<Self> {
+        debug!("database: {database}");
+        let conn = Connection::open(database).inspect_err(|e| error!("{e}"))?;
+        conn.execute_batch(
+            r#"
+        CREATE TABLE IF NOT EXISTS aliases(
+            first_name_id               INTEGER NOT NULL,
+            middle_name_id              INTEGER NOT NULL,
+            last_name_id                INTEGER NOT NULL,
+            canonical_first_name_id     INTEGER NOT NULL,
+            canonical_middle_name_id    INTEGER NOT NULL,
+            canonical_last_name_id      INTEGER NOT NULL,
+            UNIQUE(first_name_id, middle_name_id, last_name_id) ON CONFLICT REPLACE);
+        "#,
+        )?;
+
+        Ok(Self::new(conn))
+    }
+}
+impl TryFrom<&String> for AliasApi {
+    type Error = anyhow::Error;
+
+    fn try_from(database: &String) -> anyhow::Result<Self> {
+        debug!("database: {database}");
+        AliasApi::try_from(database.as_str())
+    }
+}
+
+/// Returns the ids written as `first/middle/last` like in the links of the authors
+pub fn parse_ids(value: &str) -> Option<AuthorIds> {
+    let mut parts = value.trim().split('/').map(|part| part.parse::<u32>());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(fid)), Some(Ok(mid)), Some(Ok(lid)), None) => Some((fid, mid, lid)),
+        _ => None,
+    }
+}
+
+/// Returns the pairs of the authors who are likely the same person as `(alias, canonical)`.
+/// The authors sorted by the last name have the same last name and the first and middle
+/// names are equal or abbreviated like `А.`, the author with the longer name is the canonical one.
+pub fn suggest(authors: &[Author]) -> Vec<(AuthorIds, AuthorIds)> {
+    let mut pairs = Vec::new();
+    for (idx, a) in authors.iter().enumerate() {
+        let namesakes = authors[idx + 1..]
+            .iter()
+            .take_while(|b| same_part(&a.last, &b.last, false));
+        for b in namesakes {
+            let same =
+                same_part(&a.first, &b.first, false) && same_part(&a.middle, &b.middle, true);
+            if !same || a.ids == b.ids {
+                continue;
+            }
+            let length = |author: &Author| author.to_string().chars().count();
+            match length(a) < length(b) {
+                true => pairs.push((a.ids, b.ids)),
+                false => pairs.push((b.ids, a.ids)),
+            }
+        }
+    }
+    pairs
+}
+
+/// Returns true if the parts of the names are equal or one is the initial of another,
+/// the missing part matches any if it is optional
+fn same_part(a: &str, b: &str, optional: bool) -> bool {
+    let normalize = |part: &str| {
+        part.trim()
+            .trim_end_matches('.')
+            .chars()
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    let (a, b) = (normalize(a), normalize(b));
+    if a.is_empty() || b.is_empty() {
+        return optional || a == b;
+    }
+    let initial = |short: &str, long: &str| short.chars().count() == 1 && long.starts_with(short);
+    a == b || initial(&a, &b) || initial(&b, &a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn author(ids: AuthorIds, last: &str, first: &str, middle: &str) -> Author {
+        Author {
+            ids,
+            first: String::from(first),
+            middle: String::from(middle),
+            last: String::from(last),
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let aliases = AliasApi::try_from(":memory:").unwrap();
+        aliases.merge((1, 0, 5), (2, 3, 5)).unwrap();
+        aliases.merge((2, 3, 5), (4, 3, 5)).unwrap();
+        assert_eq!((4, 3, 5), aliases.canonical((1, 0, 5)).unwrap());
+        assert_eq!((7, 7, 7), aliases.canonical((7, 7, 7)).unwrap());
+        assert_eq!(
+            vec![(4, 3, 5), (1, 0, 5), (2, 3, 5)],
+            aliases.group((2, 3, 5)).unwrap()
+        );
+        assert!(aliases.merge((4, 3, 5), (1, 0, 5)).is_err());
+
+        aliases.split((2, 3, 5)).unwrap();
+        assert_eq!(vec![(2, 3, 5)], aliases.group((2, 3, 5)).unwrap());
+        assert_eq!(vec![((1, 0, 5), (4, 3, 5))], aliases.all().unwrap());
+    }
+
+    #[test]
+    fn test_suggest() {
+        let authors = [
+            author((1, 0, 5), "Стругацкий", "А.", ""),
+            author((2, 3, 5), "Стругацкий", "Аркадий", "Натанович"),
+            author((4, 3, 5), "Стругацкий", "Борис", "Натанович"),
+            author((6, 0, 5), "Стругацкий", "", ""),
+        ];
+        assert_eq!(vec![((1, 0, 5), (2, 3, 5))], suggest(&authors));
+        assert_eq!(Some((1, 0, 5)), parse_ids("1/0/5"));
+        assert_eq!(None, parse_ids("1/0"));
+        assert_eq!(None, parse_ids("1/0/5/7"));
+    }
+}
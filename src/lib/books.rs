@@ -1,62 +1,163 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::io::{Error, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 use log::info;
 use regex::Regex;
 
-pub fn extract_book(root: PathBuf, id: u32) -> std::io::Result<PathBuf> {
-    let rx = Regex::new("fb2-([0-9]+)-([0-9]+)")
-        .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?;
-    let book_name = format!("{id}.fb2");
-    info!("book_name: {book_name}");
+/// The library archives `fb2-MIN-MAX.zip` indexed by the ranges of book ids
+#[derive(Debug, Default)]
+pub struct Index {
+    root: PathBuf,
+    archives: Vec<(u32, u32, PathBuf)>,
+    /// The CRC32 of the books by the archives, an archive is read once when its book is hashed
+    hashes: Mutex<HashMap<PathBuf, HashMap<u32, u32>>>,
+}
+impl Index {
+    /// Scan the library directory for the archives with books
+    pub fn load(root: &Path) -> io::Result<Self> {
+        let rx = Regex::new("fb2-([0-9]+)-([0-9]+)")
+            .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?;
 
-    if root.is_dir() {
-        for entry in fs::read_dir(&root)? {
-            let path = entry?.path();
-            if path.is_file() {
-                if let Some(name) = path.file_name() {
-                    let name = name.to_string_lossy();
-                    if let Some(caps) = rx.captures(&name) {
-                        let min = caps
-                            .get(1)
-                            .map_or("", |m| m.as_str())
-                            .parse::<u32>()
-                            .map_err(|err| Error::new(ErrorKind::Other, format!("{err}")))?;
-
-                        let max = caps
-                            .get(2)
-                            .map_or("", |m| m.as_str())
-                            .parse::<u32>()
-                            .map_err(|err| Error::new(ErrorKind::Other, format!("{err}")))?;
-
-                        if min <= id && id <= max {
-                            let file = fs::File::open(&path)?;
-                            let mut archive = zip::ZipArchive::new(file)?;
-                            if let Ok(mut file) = archive.by_name(&book_name) {
-                                let crc32 = file.crc32();
-                                let size = file.size();
-                                let outname = PathBuf::from(std::env::temp_dir())
-                                    .join(format!("{crc32}"))
-                                    .with_extension("fb2");
-                                info!(
-                                    "Found {} -> crc32: {crc32}, path: {} {size} B",
-                                    file.name(),
-                                    outname.display()
-                                );
-                                let mut outfile = fs::File::create(&outname)?;
-                                io::copy(&mut file, &mut outfile)?;
-                                return Ok(outname);
-                            };
-                        }
-                    }
+        let mut archives = Vec::new();
+        if root.is_dir() {
+            for entry in fs::read_dir(root)? {
+                let path = entry?.path();
+                if !path.is_file() {
+                    continue;
+                }
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if let Some(caps) = rx.captures(&name) {
+                    let min = caps[1]
+                        .parse::<u32>()
+                        .map_err(|err| Error::new(ErrorKind::Other, format!("{err}")))?;
+                    let max = caps[2]
+                        .parse::<u32>()
+                        .map_err(|err| Error::new(ErrorKind::Other, format!("{err}")))?;
+                    archives.push((min, max, path.clone()));
                 }
             }
         }
+        archives.sort();
+        info!("Found {} archives in {}", archives.len(), root.display());
+
+        Ok(Index {
+            root: root.to_path_buf(),
+            archives,
+            hashes: Mutex::default(),
+        })
+    }
+
+    /// Returns the number of indexed archives
+    pub fn len(&self) -> usize {
+        self.archives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archives.is_empty()
+    }
+
+    /// Returns the archives which may contain the book
+    pub fn find(&self, id: u32) -> impl Iterator<Item = &PathBuf> {
+        self.archives
+            .iter()
+            .filter(move |(min, max, _)| *min <= id && id <= *max)
+            .map(|(_, _, path)| path)
+    }
+}
+
+/// Returns the CRC32 of the book file stored in the archive, None if the book is not found.
+/// The CRC32 of all books of the archive are cached when it is read for the first time.
+pub fn crc32(index: &Index, id: u32) -> io::Result<Option<u32>> {
+    let mut hashes = index
+        .hashes
+        .lock()
+        .map_err(|e| io::Error::other(format!("{e}")))?;
+    for path in index.find(id) {
+        if !hashes.contains_key(path) {
+            hashes.insert(path.clone(), read_hashes(path)?);
+        }
+        if let Some(crc32) = hashes.get(path).and_then(|archive| archive.get(&id)) {
+            return Ok(Some(*crc32));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the CRC32 of the `{id}.fb2` files of the archive by the ids
+fn read_hashes(path: &Path) -> io::Result<HashMap<u32, u32>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+    let mut hashes = HashMap::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let id = file
+            .name()
+            .strip_suffix(".fb2")
+            .and_then(|id| id.parse::<u32>().ok());
+        if let Some(id) = id {
+            hashes.insert(id, file.crc32());
+        }
+    }
+    Ok(hashes)
+}
+
+pub fn extract_book(index: &Index, id: u32) -> std::io::Result<PathBuf> {
+    let book_name = format!("{id}.fb2");
+    info!("book_name: {book_name}");
+
+    for path in index.find(id) {
+        let file = fs::File::open(path)?;
+        let mut archive = zip::ZipArchive::new(file)?;
+        if let Ok(mut file) = archive.by_name(&book_name) {
+            let crc32 = file.crc32();
+            let size = file.size();
+            let outname = PathBuf::from(std::env::temp_dir())
+                .join(format!("{crc32}"))
+                .with_extension("fb2");
+            info!(
+                "Found {} -> crc32: {crc32}, path: {} {size} B",
+                file.name(),
+                outname.display()
+            );
+            let mut outfile = fs::File::create(&outname)?;
+            io::copy(&mut file, &mut outfile)?;
+            return Ok(outname);
+        };
     }
     Err(Error::new(
         ErrorKind::Other,
-        format!("The book {id} was not found in {}", root.display()),
+        format!("The book {id} was not found in {}", index.root.display()),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_crc32() {
+        let dir = std::env::temp_dir().join(format!("opds-crc32-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fb2-000001-000010.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        for name in ["5.fb2", "6.txt"] {
+            let options = zip::write::SimpleFileOptions::default();
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"book").unwrap();
+        }
+        zip.finish().unwrap();
+
+        let index = Index::load(&dir).unwrap();
+        assert_eq!(Some(0xcbe5a331), crc32(&index, 5).unwrap());
+        assert_eq!(None, crc32(&index, 6).unwrap());
+        assert_eq!(None, crc32(&index, 11).unwrap());
+        // the cached value is returned without reading the archive again
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Some(0xcbe5a331), crc32(&index, 5).unwrap());
+    }
+}
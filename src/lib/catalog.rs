@@ -0,0 +1,1058 @@
+//! Read-only queries to the books catalog which are not provided by `OpdsApi`.
+//!
+//! The queries rely on the tables listed in `TABLES`, the database is checked against them
+//! when it is opened.
+use log::{debug, error};
+use rusqlite::Connection;
+
+use crate::translit;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug)]
+pub struct Serie {
+    pub id: u32,
+    pub name: String,
+    pub num: u32,
+}
+impl fmt::Display for Serie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num > 0 {
+            write!(f, "{} #{}", self.name, self.num)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+/// The number of the parameters bound in one query, the older SQLite allows at most 999
+const MAX_PARAMS: usize = 900;
+
+/// The catalog tables and their columns used by the queries
+pub const TABLES: &[(&str, &[&str])] = &[
+    (
+        "books",
+        &[
+            "book_id",
+            "title_id",
+            "serie_id",
+            "serie_num",
+            "size",
+            "lang",
+            "added",
+            "deleted",
+        ],
+    ),
+    ("titles", &["id", "value"]),
+    ("series", &["id", "value"]),
+    ("first_names", &["id", "value"]),
+    ("middle_names", &["id", "value"]),
+    ("last_names", &["id", "value"]),
+    (
+        "authors_map",
+        &["book_id", "first_name_id", "middle_name_id", "last_name_id"],
+    ),
+    ("genres", &["id", "code", "value", "meta"]),
+    ("genres_map", &["book_id", "genre_id"]),
+];
+
+/// Fails with the first table or column of `TABLES` missing in the database
+pub fn check_schema(conn: &Connection) -> anyhow::Result<()> {
+    for (table, columns) in TABLES {
+        let mut statement = conn.prepare("SELECT name FROM pragma_table_info($1)")?;
+        let existing = statement
+            .query_map([table], |row| row.get::<_, String>(0))?
+            .collect::<Result<HashSet<_>, _>>()?;
+        if existing.is_empty() {
+            anyhow::bail!("The catalog has no table {table}");
+        }
+        if let Some(column) = columns.iter().find(|column| !existing.contains(**column)) {
+            anyhow::bail!("The catalog table {table} has no column {column}");
+        }
+    }
+    Ok(())
+}
+
+/// The gaps are not reported if more numbers are missing, e.g. the series numbered by years
+const MAX_MISSING_NUMS: usize = 10;
+
+/// Returns the numbers missing in the serie from 1 to the greatest number of its books,
+/// the books without numbers are skipped
+pub fn missing_nums<I: IntoIterator<Item = u32>>(nums: I) -> Vec<u32> {
+    let nums = nums
+        .into_iter()
+        .filter(|num| *num > 0)
+        .collect::<BTreeSet<_>>();
+    let last = nums.last().copied().unwrap_or(0);
+    let missing = (1..=last)
+        .filter(|num| !nums.contains(num))
+        .collect::<Vec<_>>();
+    match missing.len() > MAX_MISSING_NUMS {
+        true => vec![],
+        false => missing,
+    }
+}
+
+#[derive(Debug)]
+pub struct Genre {
+    pub id: u32,
+    pub name: String,
+}
+
+/// The book as it is described in the catalog
+#[derive(Debug)]
+pub struct Book {
+    pub id: u32,
+    pub title: String,
+    pub serie: Option<Serie>,
+    pub size: u32,
+    pub lang: String,
+    /// The date when the book was added to the library as `YYYY-MM-DD`
+    pub added: String,
+    /// The names of the authors as `Last First Middle`
+    pub authors: Vec<String>,
+    /// The book is marked as deleted in the library
+    pub deleted: bool,
+}
+impl fmt::Display for Book {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title)
+    }
+}
+
+const BOOK_COLUMNS: &str = r#"
+    books.book_id, titles.value, books.serie_id, series.value, IFNULL(books.serie_num, 0),
+    IFNULL(books.size, 0), IFNULL(books.lang, ''), IFNULL(books.added, ''), IFNULL(books.deleted, 0)
+"#;
+const BOOK_COLUMNS_COUNT: usize = 9;
+const BOOK_TABLES: &str = r#"
+    books JOIN titles ON titles.id = books.title_id
+    LEFT JOIN series ON series.id = books.serie_id
+"#;
+
+fn book_from_row(row: &rusqlite::Row) -> rusqlite::Result<Book> {
+    let serie = match (
+        row.get::<_, Option<u32>>(2)?,
+        row.get::<_, Option<String>>(3)?,
+    ) {
+        (Some(id), Some(name)) => Some(Serie {
+            id,
+            name,
+            num: row.get(4)?,
+        }),
+        _ => None,
+    };
+    Ok(Book {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        serie,
+        size: row.get(5)?,
+        lang: row.get(6)?,
+        added: row.get(7)?,
+        authors: Vec::new(),
+        deleted: row.get(8)?,
+    })
+}
+
+/// The author with the parts of the name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Author {
+    /// The ids of the first, middle and last names
+    pub ids: (u32, u32, u32),
+    pub first: String,
+    pub middle: String,
+    pub last: String,
+}
+impl fmt::Display for Author {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = [self.last.clone(), self.first.clone(), self.middle.clone()];
+        write!(f, "{}", full_name(parts))
+    }
+}
+
+fn author_from_row(row: &rusqlite::Row) -> rusqlite::Result<Author> {
+    Ok(Author {
+        ids: (row.get(0)?, row.get(1)?, row.get(2)?),
+        first: row.get(3)?,
+        middle: row.get(4)?,
+        last: row.get(5)?,
+    })
+}
+
+/// Returns the full name skipping the empty parts
+fn full_name(parts: [String; 3]) -> String {
+    parts
+        .iter()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug)]
+pub struct CatalogApi {
+    conn: Connection,
+    /// The books marked as deleted are not returned
+    hide_deleted: bool,
+    /// The last names of the authors indexed by the transliteration
+    last_names: translit::Index,
+    /// The names of the series indexed by the transliteration
+    serie_names: translit::Index,
+}
+impl CatalogApi {
+    /// Create CatalogApi instance
+    pub fn new(conn: Connection) -> Self {
+        CatalogApi {
+            conn,
+            hide_deleted: false,
+            last_names: translit::Index::default(),
+            serie_names: translit::Index::default(),
+        }
+    }
+
+    /// Skip the books marked as deleted in the returned books
+    pub fn set_hide_deleted(&mut self, hide: bool) {
+        self.hide_deleted = hide;
+    }
+
+    /// Fails if the books table can not be read, the cheap check of the readiness
+    pub fn check(&self) -> anyhow::Result<()> {
+        let sql = "SELECT 1 FROM books LIMIT 1;";
+        self.conn.prepare_cached(sql)?.query([])?.next()?;
+        Ok(())
+    }
+
+    /// Returns the serie of the book with its number in the serie
+    pub fn serie_by_book_id(&self, id: u32) -> anyhow::Result<Option<Serie>> {
+        let sql = r#"
+            SELECT series.id, series.value, IFNULL(books.serie_num, 0)
+            FROM books JOIN series ON series.id = books.serie_id
+            WHERE books.book_id = $1;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let mut rows = statement.query_map([id], |row| {
+            Ok(Serie {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                num: row.get(2)?,
+            })
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Returns the genres of the book
+    pub fn genres_by_book_id(&self, id: u32) -> anyhow::Result<Vec<Genre>> {
+        let sql = r#"
+            SELECT genres.id, genres.value
+            FROM genres_map JOIN genres ON genres.id = genres_map.genre_id
+            WHERE genres_map.book_id = $1
+            ORDER BY genres.value;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([id], |row| {
+            Ok(Genre {
+                id: row.get(0)?,
+                name: row.get(1)?,
+            })
+        })?;
+
+        let mut genres = Vec::new();
+        for genre in rows {
+            genres.push(genre?);
+        }
+        Ok(genres)
+    }
+
+    /// Returns the genres of the author with the number of the author's books in each genre
+    pub fn genres_by_author_ids(
+        &self,
+        fid: u32,
+        mid: u32,
+        lid: u32,
+    ) -> anyhow::Result<Vec<(Genre, u32)>> {
+        let sql = r#"
+            SELECT genres.id, genres.value, COUNT(DISTINCT authors_map.book_id)
+            FROM authors_map
+            JOIN genres_map ON genres_map.book_id = authors_map.book_id
+            JOIN genres ON genres.id = genres_map.genre_id
+            WHERE authors_map.first_name_id = $1
+              AND authors_map.middle_name_id = $2
+              AND authors_map.last_name_id = $3
+            GROUP BY genres.id
+            ORDER BY genres.value;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([fid, mid, lid], |row| {
+            let genre = Genre {
+                id: row.get(0)?,
+                name: row.get(1)?,
+            };
+            Ok((genre, row.get(2)?))
+        })?;
+
+        let mut genres = Vec::new();
+        for genre in rows {
+            genres.push(genre?);
+        }
+        Ok(genres)
+    }
+
+    /// Returns ids of the author's books in the genre
+    pub fn books_ids_by_author_ids_and_genre_id(
+        &self,
+        fid: u32,
+        mid: u32,
+        lid: u32,
+        gid: u32,
+    ) -> anyhow::Result<Vec<u32>> {
+        let sql = r#"
+            SELECT DISTINCT authors_map.book_id
+            FROM authors_map
+            JOIN genres_map ON genres_map.book_id = authors_map.book_id
+            WHERE authors_map.first_name_id = $1
+              AND authors_map.middle_name_id = $2
+              AND authors_map.last_name_id = $3
+              AND genres_map.genre_id = $4;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([fid, mid, lid, gid], |row| row.get(0))?;
+
+        let mut ids = Vec::new();
+        for id in rows {
+            ids.push(id?);
+        }
+        Ok(ids)
+    }
+
+    /// Returns years when books in the genre were added with the number of books, latest first
+    pub fn years_by_genre_id(&self, gid: u32) -> anyhow::Result<Vec<(u32, u32)>> {
+        let sql = r#"
+            SELECT CAST(substr(books.added, 1, 4) AS INTEGER) AS year, COUNT(*)
+            FROM books JOIN genres_map ON genres_map.book_id = books.book_id
+            WHERE genres_map.genre_id = $1 AND books.added IS NOT NULL
+            GROUP BY year
+            ORDER BY year DESC;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([gid], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut years = Vec::new();
+        for year in rows {
+            years.push(year?);
+        }
+        Ok(years)
+    }
+
+    /// Returns months of the year when books in the genre were added with the number of books
+    pub fn months_by_genre_id_and_year(
+        &self,
+        gid: u32,
+        year: u32,
+    ) -> anyhow::Result<Vec<(u32, u32)>> {
+        let sql = r#"
+            SELECT CAST(substr(books.added, 6, 2) AS INTEGER) AS month, COUNT(*)
+            FROM books JOIN genres_map ON genres_map.book_id = books.book_id
+            WHERE genres_map.genre_id = $1 AND substr(books.added, 1, 4) = $2
+            GROUP BY month
+            ORDER BY month DESC;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let year = format!("{year:04}");
+        let rows = statement.query_map((gid, year), |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut months = Vec::new();
+        for month in rows {
+            months.push(month?);
+        }
+        Ok(months)
+    }
+
+    /// Returns the condition of the listed books to put in the WHERE of the queries,
+    /// so the deleted books are skipped before LIMIT and counted by none
+    fn visible(&self) -> &'static str {
+        match self.hide_deleted {
+            true => "IFNULL(books.deleted, 0) = 0",
+            false => "1",
+        }
+    }
+
+    /// Returns the books found by the query with `BOOK_COLUMNS`, the authors are loaded too
+    fn books<P: rusqlite::Params>(&self, sql: &str, params: P) -> anyhow::Result<Vec<Book>> {
+        let books = self.books_with(sql, params, |_| Ok(()))?;
+        Ok(books.into_iter().map(|(book, _)| book).collect())
+    }
+
+    /// Returns the books with the values of the columns following `BOOK_COLUMNS`
+    fn books_with<P, T, F>(&self, sql: &str, params: P, extra: F) -> anyhow::Result<Vec<(Book, T)>>
+    where
+        P: rusqlite::Params,
+        F: Fn(&rusqlite::Row) -> rusqlite::Result<T>,
+    {
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map(params, |row| Ok((book_from_row(row)?, extra(row)?)))?;
+
+        let mut books = Vec::new();
+        for row in rows {
+            let (mut book, value) = row?;
+            book.authors = self.authors_names_by_book_id(book.id)?;
+            books.push((book, value));
+        }
+        Ok(books)
+    }
+
+    /// Returns the names of the book's authors
+    pub fn authors_names_by_book_id(&self, id: u32) -> anyhow::Result<Vec<String>> {
+        let sql = r#"
+            SELECT last_names.value, first_names.value, middle_names.value
+            FROM authors_map
+            JOIN first_names ON first_names.id = authors_map.first_name_id
+            JOIN middle_names ON middle_names.id = authors_map.middle_name_id
+            JOIN last_names ON last_names.id = authors_map.last_name_id
+            WHERE authors_map.book_id = $1
+            ORDER BY last_names.value, first_names.value;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([id], |row| {
+            Ok(full_name([row.get(0)?, row.get(1)?, row.get(2)?]))
+        })?;
+
+        let mut names = Vec::new();
+        for name in rows {
+            names.push(name?);
+        }
+        Ok(names)
+    }
+
+    /// Returns the names of the authors of each book
+    fn authors_names_by_books_ids(&self, ids: &[u32]) -> anyhow::Result<HashMap<u32, Vec<String>>> {
+        let mut names = HashMap::new();
+        for chunk in ids.chunks(MAX_PARAMS) {
+            let sql = format!(
+                r#"
+                SELECT authors_map.book_id, last_names.value, first_names.value, middle_names.value
+                FROM authors_map
+                JOIN first_names ON first_names.id = authors_map.first_name_id
+                JOIN middle_names ON middle_names.id = authors_map.middle_name_id
+                JOIN last_names ON last_names.id = authors_map.last_name_id
+                WHERE authors_map.book_id IN ({})
+                ORDER BY last_names.value, first_names.value;
+            "#,
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut statement = self.conn.prepare(&sql)?;
+            let mut rows = statement.query(rusqlite::params_from_iter(chunk))?;
+            while let Some(row) = rows.next()? {
+                names
+                    .entry(row.get(0)?)
+                    .or_insert_with(Vec::new)
+                    .push(full_name([row.get(1)?, row.get(2)?, row.get(3)?]));
+            }
+        }
+        Ok(names)
+    }
+
+    /// Returns the name of the author by the ids of the name parts
+    pub fn author_name_by_ids(
+        &self,
+        fid: u32,
+        mid: u32,
+        lid: u32,
+    ) -> anyhow::Result<Option<String>> {
+        let author = self.author_by_ids(fid, mid, lid)?;
+        Ok(author.map(|author| author.to_string()))
+    }
+
+    /// Returns the author by the ids of the name parts
+    pub fn author_by_ids(&self, fid: u32, mid: u32, lid: u32) -> anyhow::Result<Option<Author>> {
+        let sql = r#"
+            SELECT first_names.id, middle_names.id, last_names.id,
+                first_names.value, middle_names.value, last_names.value
+            FROM first_names, middle_names, last_names
+            WHERE first_names.id = $1 AND middle_names.id = $2 AND last_names.id = $3;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let mut rows = statement.query_map([fid, mid, lid], author_from_row)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Returns the authors sharing the last name with other authors, the candidates for aliases
+    pub fn authors_with_namesakes(&self) -> anyhow::Result<Vec<Author>> {
+        let sql = r#"
+            WITH authors AS (
+                SELECT DISTINCT first_name_id, middle_name_id, last_name_id FROM authors_map)
+            SELECT authors.first_name_id, authors.middle_name_id, authors.last_name_id,
+                first_names.value, middle_names.value, last_names.value
+            FROM authors
+            JOIN first_names ON first_names.id = authors.first_name_id
+            JOIN middle_names ON middle_names.id = authors.middle_name_id
+            JOIN last_names ON last_names.id = authors.last_name_id
+            WHERE authors.last_name_id IN (
+                SELECT last_name_id FROM authors GROUP BY last_name_id HAVING COUNT(*) > 1)
+            ORDER BY last_names.value, first_names.value, middle_names.value;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([], author_from_row)?;
+
+        let mut authors = Vec::new();
+        for author in rows {
+            authors.push(author?);
+        }
+        Ok(authors)
+    }
+
+    /// Index the names of the authors and the series by the transliteration,
+    /// the catalog is searched by the indexes until they are built again
+    pub fn index_names(&mut self) -> anyhow::Result<()> {
+        self.last_names = translit::Index::new(self.last_names()?);
+        self.serie_names = translit::Index::new(self.serie_names()?);
+        Ok(())
+    }
+
+    /// Returns the last names of the authors indexed by the transliteration
+    pub fn last_names_index(&self) -> &translit::Index {
+        &self.last_names
+    }
+
+    /// Returns the names of the series indexed by the transliteration
+    pub fn serie_names_index(&self) -> &translit::Index {
+        &self.serie_names
+    }
+
+    /// Returns the last names of the authors
+    pub fn last_names(&self) -> anyhow::Result<Vec<String>> {
+        self.values("SELECT DISTINCT value FROM last_names ORDER BY value;")
+    }
+
+    /// Returns the names of the series
+    pub fn serie_names(&self) -> anyhow::Result<Vec<String>> {
+        self.values("SELECT DISTINCT value FROM series ORDER BY value;")
+    }
+
+    fn values(&self, sql: &str) -> anyhow::Result<Vec<String>> {
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let rows = statement.query_map([], |row| row.get(0))?;
+
+        let mut values = Vec::new();
+        for value in rows {
+            values.push(value?);
+        }
+        Ok(values)
+    }
+
+    /// Returns the genre by id
+    pub fn genre_by_id(&self, gid: u32) -> anyhow::Result<Option<Genre>> {
+        let sql = "SELECT id, value FROM genres WHERE id = $1;";
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let mut rows = statement.query_map([gid], |row| {
+            Ok(Genre {
+                id: row.get(0)?,
+                name: row.get(1)?,
+            })
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Returns the latest books added in the genre, the newest first
+    pub fn latest_books_by_genre_id(&self, gid: u32, limit: u32) -> anyhow::Result<Vec<Book>> {
+        let sql = format!(
+            r#"
+            SELECT {BOOK_COLUMNS}
+            FROM {BOOK_TABLES} JOIN genres_map ON genres_map.book_id = books.book_id
+            WHERE genres_map.genre_id = $1 AND {visible}
+            ORDER BY books.added DESC, books.book_id DESC
+            LIMIT $2;
+        "#,
+            visible = self.visible()
+        );
+        self.books(&sql, [gid, limit])
+    }
+
+    /// Returns the latest books of the author, the newest first
+    pub fn latest_books_by_author_ids(
+        &self,
+        fid: u32,
+        mid: u32,
+        lid: u32,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Book>> {
+        let sql = format!(
+            r#"
+            SELECT {BOOK_COLUMNS}
+            FROM {BOOK_TABLES} JOIN authors_map ON authors_map.book_id = books.book_id
+            WHERE authors_map.first_name_id = $1
+              AND authors_map.middle_name_id = $2
+              AND authors_map.last_name_id = $3
+              AND {visible}
+            ORDER BY books.added DESC, books.book_id DESC
+            LIMIT $4;
+        "#,
+            visible = self.visible()
+        );
+        self.books(&sql, [fid, mid, lid, limit])
+    }
+
+    /// Returns the books added for the last `days` days, the newest first,
+    /// only the books in the language if it is given
+    pub fn new_books(
+        &self,
+        days: u32,
+        lang: Option<&str>,
+        offset: u32,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Book>> {
+        let sql = format!(
+            r#"
+            SELECT {BOOK_COLUMNS}
+            FROM {BOOK_TABLES}
+            WHERE books.added >= DATE('now', 'localtime', $1)
+              AND ($2 IS NULL OR LOWER(books.lang) = $2)
+              AND {visible}
+            ORDER BY books.added DESC, books.book_id DESC
+            LIMIT $3 OFFSET $4;
+        "#,
+            visible = self.visible()
+        );
+        let days = format!("-{days} days");
+        self.books(&sql, rusqlite::params![days, lang, limit, offset])
+    }
+
+    /// Returns the books added on the day `YYYY-MM-DD`, only the books in the language if it is given
+    pub fn books_added_on(
+        &self,
+        date: &str,
+        lang: Option<&str>,
+        offset: u32,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Book>> {
+        let sql = format!(
+            r#"
+            SELECT {BOOK_COLUMNS}
+            FROM {BOOK_TABLES}
+            WHERE books.added = $1
+              AND ($2 IS NULL OR LOWER(books.lang) = $2)
+              AND {visible}
+            ORDER BY books.book_id DESC
+            LIMIT $3 OFFSET $4;
+        "#,
+            visible = self.visible()
+        );
+        self.books(&sql, rusqlite::params![date, lang, limit, offset])
+    }
+
+    /// Returns the languages of the books added for the last `days` days with the number of books
+    pub fn new_books_langs(&self, days: u32) -> anyhow::Result<Vec<(String, u32)>> {
+        let sql = format!(
+            r#"
+            SELECT LOWER(IFNULL(books.lang, '')) AS code, COUNT(*)
+            FROM books
+            WHERE books.added >= DATE('now', 'localtime', $1) AND {visible}
+            GROUP BY code
+            ORDER BY COUNT(*) DESC, code;
+        "#,
+            visible = self.visible()
+        );
+        let days = format!("-{days} days");
+        let mut statement = self.conn.prepare_cached(&sql)?;
+        let rows = statement.query_map([days], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut langs = Vec::new();
+        for lang in rows {
+            langs.push(lang?);
+        }
+        Ok(langs)
+    }
+
+    /// Returns the books added for the last `days` days with their genres ordered by genre,
+    /// the book of several genres is returned for each of them
+    pub fn new_books_by_genre(
+        &self,
+        days: u32,
+        lang: Option<&str>,
+        offset: u32,
+        limit: u32,
+    ) -> anyhow::Result<Vec<(Book, Genre)>> {
+        let sql = format!(
+            r#"
+            SELECT {BOOK_COLUMNS}, genres.id, genres.value
+            FROM {BOOK_TABLES}
+            JOIN genres_map ON genres_map.book_id = books.book_id
+            JOIN genres ON genres.id = genres_map.genre_id
+            WHERE books.added >= DATE('now', 'localtime', $1)
+              AND ($2 IS NULL OR LOWER(books.lang) = $2)
+              AND {visible}
+            ORDER BY genres.value, books.added DESC, books.book_id DESC
+            LIMIT $3 OFFSET $4;
+        "#,
+            visible = self.visible()
+        );
+        let days = format!("-{days} days");
+        let params = rusqlite::params![days, lang, limit, offset];
+        self.books_with(&sql, params, |row| {
+            Ok(Genre {
+                id: row.get(BOOK_COLUMNS_COUNT)?,
+                name: row.get(BOOK_COLUMNS_COUNT + 1)?,
+            })
+        })
+    }
+
+    /// Returns the languages of the books of each author
+    pub fn langs_by_authors_ids(
+        &self,
+        ids: &[(u32, u32, u32)],
+    ) -> anyhow::Result<HashMap<(u32, u32, u32), Vec<String>>> {
+        let mut langs = HashMap::new();
+        for chunk in ids.chunks(MAX_PARAMS / 3) {
+            let sql = format!(
+                r#"
+                SELECT DISTINCT authors_map.first_name_id, authors_map.middle_name_id,
+                    authors_map.last_name_id, LOWER(IFNULL(books.lang, ''))
+                FROM authors_map JOIN books ON books.book_id = authors_map.book_id
+                WHERE (authors_map.first_name_id, authors_map.middle_name_id,
+                    authors_map.last_name_id) IN (VALUES {});
+            "#,
+                vec!["(?, ?, ?)"; chunk.len()].join(", ")
+            );
+            let mut statement = self.conn.prepare(&sql)?;
+            let params = chunk.iter().flat_map(|&(fid, mid, lid)| [fid, mid, lid]);
+            let mut rows = statement.query(rusqlite::params_from_iter(params))?;
+            while let Some(row) = rows.next()? {
+                let key = (row.get(0)?, row.get(1)?, row.get(2)?);
+                langs.entry(key).or_insert_with(Vec::new).push(row.get(3)?);
+            }
+        }
+        Ok(langs)
+    }
+
+    /// Returns the languages of the books of each serie
+    pub fn langs_by_series_ids(&self, ids: &[u32]) -> anyhow::Result<HashMap<u32, Vec<String>>> {
+        let mut langs = HashMap::new();
+        for chunk in ids.chunks(MAX_PARAMS) {
+            let sql = format!(
+                "SELECT DISTINCT serie_id, LOWER(IFNULL(lang, '')) FROM books WHERE serie_id IN ({});",
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut statement = self.conn.prepare(&sql)?;
+            let mut rows = statement.query(rusqlite::params_from_iter(chunk))?;
+            while let Some(row) = rows.next()? {
+                langs
+                    .entry(row.get(0)?)
+                    .or_insert_with(Vec::new)
+                    .push(row.get(1)?);
+            }
+        }
+        Ok(langs)
+    }
+
+    /// Returns the languages of all books, optionally of the genre, with the number of books
+    pub fn langs(&self, gid: Option<u32>) -> anyhow::Result<Vec<(String, u32)>> {
+        let sql = format!(
+            r#"
+            SELECT LOWER(IFNULL(books.lang, '')) AS code, COUNT(*)
+            FROM books
+            WHERE ($1 IS NULL OR EXISTS (
+                SELECT 1 FROM genres_map
+                WHERE genres_map.book_id = books.book_id AND genres_map.genre_id = $1))
+              AND {visible}
+            GROUP BY code
+            ORDER BY COUNT(*) DESC, code;
+        "#,
+            visible = self.visible()
+        );
+        let mut statement = self.conn.prepare_cached(&sql)?;
+        let rows = statement.query_map([gid], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut langs = Vec::new();
+        for lang in rows {
+            langs.push(lang?);
+        }
+        Ok(langs)
+    }
+
+    /// Returns the book by id
+    pub fn book_by_id(&self, id: u32) -> anyhow::Result<Option<Book>> {
+        let visible = self.visible();
+        let sql = format!(
+            "SELECT {BOOK_COLUMNS} FROM {BOOK_TABLES} WHERE books.book_id = $1 AND {visible};"
+        );
+        Ok(self.books(&sql, [id])?.pop())
+    }
+
+    /// Returns the books in the order of ids, the missing books are skipped
+    /// Returns the books in the order of the ids, the missing and hidden books are skipped
+    pub fn books_by_ids(&self, ids: &[u32]) -> anyhow::Result<Vec<Book>> {
+        let visible = self.visible();
+        let mut found = HashMap::with_capacity(ids.len());
+        for chunk in ids.chunks(MAX_PARAMS) {
+            let sql = format!(
+                "SELECT {BOOK_COLUMNS} FROM {BOOK_TABLES} WHERE books.book_id IN ({}) AND {visible};",
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut statement = self.conn.prepare(&sql)?;
+            let rows = statement.query_map(rusqlite::params_from_iter(chunk), book_from_row)?;
+            for book in rows {
+                let book = book?;
+                found.insert(book.id, book);
+            }
+        }
+        let found_ids = found.keys().copied().collect::<Vec<_>>();
+        let mut authors = self.authors_names_by_books_ids(&found_ids)?;
+        let books = ids
+            .iter()
+            .filter_map(|id| found.remove(id))
+            .map(|mut book| {
+                book.authors = authors.remove(&book.id).unwrap_or_default();
+                book
+            })
+            .collect();
+        Ok(books)
+    }
+
+    /// Returns up to `count` random books, optionally of the genre and in the language.
+    /// The random ids are checked one by one, so the whole table is not sorted, and
+    /// the books which are not found this way are picked at the random offsets of the
+    /// matching books. Every matching book is equally likely, the books for which
+    /// `exclude` returns true are skipped.
+    pub fn random_books<F>(
+        &self,
+        count: usize,
+        gid: Option<u32>,
+        lang: Option<&str>,
+        exclude: F,
+    ) -> anyhow::Result<Vec<Book>>
+    where
+        F: Fn(u32) -> bool,
+    {
+        let sql = "SELECT MIN(book_id), MAX(book_id) FROM books;";
+        let range: (Option<u32>, Option<u32>) = self
+            .conn
+            .prepare_cached(sql)?
+            .query_row([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let (Some(min), Some(max)) = range else {
+            return Ok(Vec::new());
+        };
+
+        // the parameters are numbered in the order they appear in the statements
+        let filter = format!(
+            r#"
+            ($1 IS NULL OR LOWER(books.lang) = $1)
+            AND ($2 IS NULL OR EXISTS (
+                SELECT 1 FROM genres_map
+                WHERE genres_map.book_id = books.book_id AND genres_map.genre_id = $2))
+            AND {visible}
+        "#,
+            visible = self.visible()
+        );
+        let mut ids = Vec::new();
+        let mut push = |id: u32| {
+            if !ids.contains(&id) && !exclude(id) {
+                ids.push(id);
+            }
+            ids.len() >= count
+        };
+
+        let sql = format!("SELECT EXISTS(SELECT 1 FROM books WHERE {filter} AND book_id = $3);");
+        let mut statement = self.conn.prepare_cached(&sql)?;
+        for _ in 0..count * 8 {
+            let id = fastrand::u32(min..=max);
+            let found: bool =
+                statement.query_row(rusqlite::params![lang, gid, id], |row| row.get(0))?;
+            if found && push(id) {
+                return self.books_by_ids(&ids);
+            }
+        }
+
+        // the matching books are sparse among the ids, they are sampled from all matching ids
+        let sql = format!("SELECT book_id FROM books WHERE {filter};");
+        let mut statement = self.conn.prepare_cached(&sql)?;
+        let mut candidates = statement
+            .query_map(rusqlite::params![lang, gid], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, _>>()?;
+        let len = candidates.len();
+        for i in 0..len {
+            // the partial shuffle, only the picked ids are moved to the front
+            candidates.swap(i, fastrand::usize(i..len));
+            if push(candidates[i]) {
+                break;
+            }
+        }
+
+        self.books_by_ids(&ids)
+    }
+}
+
+impl TryFrom<&str> for CatalogApi {
+    type Error = anyhow::Error;
+
+    fn try_from(database: &str) -> anyhow::Result<Self> {
+        debug!("database: {database}");
+        let conn = Connection::open(database).inspect_err(|e| error!("{e}"))?;
+        check_schema(&conn).inspect_err(|e| error!("{database}: {e}"))?;
+        Ok(Self::new(conn))
+    }
+}
+impl TryFrom<&String> for CatalogApi {
+    type Error = anyhow::Error;
+
+    fn try_from(database: &String) -> anyhow::Result<Self> {
+        debug!("database: {database}");
+        CatalogApi::try_from(database.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::SCHEMA;
+
+    #[test]
+    fn test_check_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(check_schema(&conn).is_err());
+        conn.execute_batch(SCHEMA).unwrap();
+        check_schema(&conn).unwrap();
+        conn.execute_batch("ALTER TABLE books DROP COLUMN deleted;")
+            .unwrap();
+        let error = check_schema(&conn).unwrap_err();
+        assert_eq!(
+            "The catalog table books has no column deleted",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_missing_nums() {
+        assert_eq!(vec![1, 4], missing_nums([3, 2, 0, 5, 2]));
+        assert!(missing_nums([0, 0]).is_empty());
+        assert!(missing_nums([1, 2, 3]).is_empty());
+        assert!(missing_nums([1, 2024]).is_empty());
+    }
+
+    #[test]
+    fn test_random_books() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        conn.execute("INSERT INTO titles VALUES(1, 'Title');", [])
+            .unwrap();
+        for id in 1..=100 {
+            let lang = if id % 10 == 0 { "en" } else { "ru" };
+            let sql = "INSERT INTO books VALUES($1, 1, NULL, NULL, 100, $2, '2024-01-01', 0);";
+            conn.execute(sql, rusqlite::params![id, lang]).unwrap();
+        }
+        let catalog = CatalogApi::new(conn);
+
+        let books = catalog.random_books(5, None, None, |_| false).unwrap();
+        assert_eq!(5, books.len());
+
+        // only 10 books are in English, 3 of them are excluded
+        let books = catalog
+            .random_books(20, None, Some("en"), |id| id <= 30)
+            .unwrap();
+        assert!(books.len() <= 7);
+        assert!(books.iter().all(|book| book.lang == "en" && book.id > 30));
+
+        let books = catalog.random_books(5, Some(1), None, |_| false).unwrap();
+        assert!(books.is_empty());
+    }
+
+    #[test]
+    fn test_langs() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        let sql = r#"
+            INSERT INTO titles VALUES(1, 'Title');
+            INSERT INTO books VALUES(1, 1, 1, 1, 100, 'ru', '2024-01-01', 0);
+            INSERT INTO books VALUES(2, 1, 1, 2, 100, 'UK', '2024-01-01', 0);
+            INSERT INTO books VALUES(3, 1, NULL, NULL, 100, 'ru', '2024-01-01', 0);
+            INSERT INTO authors_map VALUES(1, 1, 1, 1);
+            INSERT INTO authors_map VALUES(2, 1, 1, 1);
+            INSERT INTO authors_map VALUES(3, 2, 2, 2);
+            INSERT INTO genres_map VALUES(2, 5);
+        "#;
+        conn.execute_batch(sql).unwrap();
+        let catalog = CatalogApi::new(conn);
+
+        let books = catalog.books_by_ids(&[2, 4, 1]).unwrap();
+        let ids = books.iter().map(|book| book.id).collect::<Vec<_>>();
+        assert_eq!(vec![2, 1], ids);
+
+        let langs = catalog
+            .langs_by_authors_ids(&[(1, 1, 1), (2, 2, 2)])
+            .unwrap();
+        let mut first = langs[&(1, 1, 1)].clone();
+        first.sort();
+        assert_eq!(vec!["ru", "uk"], first);
+        assert_eq!(vec!["ru"], langs[&(2, 2, 2)]);
+
+        let langs = catalog.langs_by_series_ids(&[1]).unwrap();
+        assert_eq!(2, langs[&1].len());
+
+        let langs = catalog.langs(None).unwrap();
+        assert_eq!(
+            vec![(String::from("ru"), 2), (String::from("uk"), 1)],
+            langs
+        );
+        let langs = catalog.langs(Some(5)).unwrap();
+        assert_eq!(vec![(String::from("uk"), 1)], langs);
+    }
+
+    #[test]
+    fn test_authors() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        let sql = r#"
+            INSERT INTO first_names VALUES(1, 'А.'), (2, 'Аркадий'), (3, 'Станислав');
+            INSERT INTO middle_names VALUES(1, ''), (2, 'Натанович');
+            INSERT INTO last_names VALUES(1, 'Стругацкий'), (2, 'Лем');
+            INSERT INTO authors_map VALUES(1, 1, 1, 1), (2, 2, 2, 1), (3, 2, 2, 1);
+            INSERT INTO authors_map VALUES(4, 3, 1, 2);
+        "#;
+        conn.execute_batch(sql).unwrap();
+        let mut catalog = CatalogApi::new(conn);
+
+        let author = catalog.author_by_ids(2, 2, 1).unwrap().unwrap();
+        assert_eq!("Стругацкий Аркадий Натанович", author.to_string());
+        let authors = catalog.authors_with_namesakes().unwrap();
+        let ids = authors.iter().map(|author| author.ids).collect::<Vec<_>>();
+        assert_eq!(vec![(1, 1, 1), (2, 2, 1)], ids);
+        assert_eq!(vec!["Лем", "Стругацкий"], catalog.last_names().unwrap());
+        assert!(catalog.last_names_index().search("lem", 10).is_empty());
+        catalog.index_names().unwrap();
+        assert_eq!(vec!["Лем"], catalog.last_names_index().search("lem", 10));
+    }
+
+    #[test]
+    fn test_deleted() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        let sql = r#"
+            INSERT INTO titles VALUES(1, 'Title');
+            INSERT INTO books VALUES(1, 1, NULL, NULL, 100, 'ru', '2024-01-01', 0);
+            INSERT INTO books VALUES(2, 1, NULL, NULL, 100, 'ru', '2024-01-01', 1);
+            INSERT INTO books VALUES(3, 1, NULL, NULL, 100, 'ru', '2024-01-01', 0);
+        "#;
+        conn.execute_batch(sql).unwrap();
+        let mut catalog = CatalogApi::new(conn);
+
+        let books = catalog.books_by_ids(&[1, 2]).unwrap();
+        assert_eq!(
+            vec![false, true],
+            books.iter().map(|book| book.deleted).collect::<Vec<_>>()
+        );
+        catalog.set_hide_deleted(true);
+        let books = catalog.books_by_ids(&[1, 2]).unwrap();
+        assert_eq!(
+            vec![1],
+            books.iter().map(|book| book.id).collect::<Vec<_>>()
+        );
+        // the deleted books are skipped before the limit
+        let books = catalog.books_added_on("2024-01-01", None, 0, 2).unwrap();
+        assert_eq!(
+            vec![3, 1],
+            books.iter().map(|book| book.id).collect::<Vec<_>>()
+        );
+        let langs = catalog.langs(None).unwrap();
+        assert_eq!(vec![(String::from("ru"), 2)], langs);
+    }
+}
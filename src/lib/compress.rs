@@ -0,0 +1,158 @@
+//! Response compression negotiated by `Accept-Encoding`.
+//!
+//! The actix `Compress` middleware is wrapped to compress only the textual responses
+//! (feeds, HTML, JSON) which are not smaller than the configured threshold.
+//! The skipped responses are marked by `Content-Encoding: identity` for `Compress`
+//! and the mark is removed before the response is sent.
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Compress;
+use actix_web::Error;
+use futures::future::LocalBoxFuture;
+
+use std::rc::Rc;
+
+const IDENTITY: &str = "identity";
+
+/// Returns true if the content type is worth to compress
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime.as_str(),
+            "application/xml" | "application/json" | "application/javascript"
+        )
+}
+
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: u64,
+}
+impl Compression {
+    /// Compress the responses which are not smaller than `min_size` bytes
+    pub fn new(min_size: u64) -> Self {
+        Compression { min_size }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Compression
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = <Compress as Transform<Filter<S>, ServiceRequest>>::Response;
+    type Error = <Compress as Transform<Filter<S>, ServiceRequest>>::Error;
+    type InitError = <Compress as Transform<Filter<S>, ServiceRequest>>::InitError;
+    type Transform = Unmark<<Compress as Transform<Filter<S>, ServiceRequest>>::Transform>;
+    type Future = LocalBoxFuture<'static, Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let filter = Filter {
+            service: Rc::new(service),
+            min_size: self.min_size,
+        };
+        let compress = Compress::default().new_transform(filter);
+        Box::pin(async move {
+            let service = compress.await?;
+            Ok(Unmark { service })
+        })
+    }
+}
+
+/// Marks the responses which should not be compressed
+pub struct Filter<S> {
+    service: Rc<S>,
+    min_size: u64,
+}
+
+impl<S, B> Service<ServiceRequest> for Filter<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let min_size = self.min_size;
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            let compressible = res
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(is_compressible);
+            let small = match res.response().body().size() {
+                BodySize::Sized(size) => size < min_size,
+                _ => false,
+            };
+            if !compressible || small {
+                let headers = res.headers_mut();
+                if !headers.contains_key(header::CONTENT_ENCODING) {
+                    let value = HeaderValue::from_static(IDENTITY);
+                    headers.insert(header::CONTENT_ENCODING, value);
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Removes the marks left by `Filter`
+pub struct Unmark<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for Unmark<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let response = self.service.call(req);
+        Box::pin(async move {
+            let mut res = response.await?;
+            let headers = res.headers_mut();
+            if headers
+                .get(header::CONTENT_ENCODING)
+                .is_some_and(|value| value == IDENTITY)
+            {
+                headers.remove(header::CONTENT_ENCODING);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_compressible() {
+        assert!(is_compressible("application/atom+xml;profile=opds-catalog"));
+        assert!(is_compressible("text/html; charset=utf-8"));
+        assert!(is_compressible("application/json"));
+        assert!(!is_compressible("application/zip"));
+        assert!(!is_compressible("application/epub+zip"));
+        assert!(!is_compressible("application/octet-stream"));
+    }
+}
@@ -0,0 +1,159 @@
+//! The editions of the same book uploaded to the library several times.
+//!
+//! The editions have the same title, authors and serie after the normalization and
+//! optionally the same content hash. The lists show the first edition of the book
+//! with the links to the other ones.
+use crate::catalog::Book;
+
+use std::collections::HashMap;
+
+/// How the editions of the book are detected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Detection {
+    /// Every edition is listed separately
+    Off,
+    /// The same title, authors and serie
+    #[default]
+    Title,
+    /// The same title, authors and serie and the same content of the file
+    Hash,
+}
+impl From<&str> for Detection {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "off" | "no" | "false" => Detection::Off,
+            "hash" => Detection::Hash,
+            _ => Detection::Title,
+        }
+    }
+}
+
+/// The book with its other editions
+#[derive(Debug)]
+pub struct Edition {
+    pub book: Book,
+    pub others: Vec<Book>,
+}
+impl From<Book> for Edition {
+    fn from(book: Book) -> Self {
+        Edition {
+            book,
+            others: Vec::new(),
+        }
+    }
+}
+
+/// Returns the key which is the same for the editions of the book
+pub fn key(book: &Book) -> String {
+    let mut authors = book
+        .authors
+        .iter()
+        .map(|author| normalize(author))
+        .collect::<Vec<_>>();
+    authors.sort();
+    let serie = match &book.serie {
+        Some(serie) => format!("{} {}", normalize(&serie.name), serie.num),
+        None => String::new(),
+    };
+    format!("{}|{}|{serie}", normalize(&book.title), authors.join(","))
+}
+
+/// Returns the lowercase words of letters and digits separated by single spaces
+fn normalize(value: &str) -> String {
+    let value = value
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'ё' => 'е',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect::<String>();
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Collapse the editions of the books into the first one keeping the order.
+/// The books with the same key are also compared by `hash`, it is called
+/// only for the books which have the editions.
+pub fn collapse<F>(books: Vec<Book>, hash: F) -> Vec<Edition>
+where
+    F: Fn(&Book) -> Option<u32>,
+{
+    let keys = books.iter().map(key).collect::<Vec<_>>();
+    let mut counts = HashMap::new();
+    for key in keys.iter() {
+        *counts.entry(key.as_str()).or_insert(0) += 1;
+    }
+
+    let mut editions: Vec<Edition> = Vec::new();
+    let mut firsts: HashMap<(&str, Option<u32>), usize> = HashMap::new();
+    for (book, key) in books.into_iter().zip(keys.iter()) {
+        if counts[key.as_str()] < 2 {
+            editions.push(Edition::from(book));
+            continue;
+        }
+        let key = (key.as_str(), hash(&book));
+        match firsts.get(&key) {
+            Some(&idx) => editions[idx].others.push(book),
+            None => {
+                firsts.insert(key, editions.len());
+                editions.push(Edition::from(book));
+            }
+        }
+    }
+    editions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Serie;
+
+    fn book(id: u32, title: &str, author: &str, serie: Option<u32>) -> Book {
+        Book {
+            id,
+            title: String::from(title),
+            serie: serie.map(|num| Serie {
+                id: 1,
+                name: String::from("Мир Полудня"),
+                num,
+            }),
+            size: 100,
+            lang: String::from("ru"),
+            added: String::from("2024-01-01"),
+            authors: vec![String::from(author)],
+            deleted: false,
+        }
+    }
+
+    #[test]
+    fn test_collapse() {
+        let books = vec![
+            book(1, "Трудно быть богом", "Стругацкий Аркадий", Some(3)),
+            book(2, "Жук в муравейнике", "Стругацкий Аркадий", Some(5)),
+            book(3, "Трудно  быть Богом!", "стругацкий аркадий", Some(3)),
+            book(4, "Трудно быть богом", "Стругацкий Аркадий", None),
+            book(5, "Трудно быть богом", "Стругацкий Аркадий", Some(3)),
+        ];
+        let editions = collapse(books, |_| None);
+        let ids = editions
+            .iter()
+            .map(|edition| {
+                let others = edition.others.iter().map(|book| book.id);
+                (edition.book.id, others.collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(1, vec![3, 5]), (2, vec![]), (4, vec![])], ids);
+
+        let books = vec![
+            book(1, "Пикник на обочине", "Стругацкий Аркадий", None),
+            book(2, "Пикник на обочине", "Стругацкий Аркадий", None),
+            book(3, "Пикник на обочине", "Стругацкий Аркадий", None),
+        ];
+        let editions = collapse(books, |book| Some(book.id % 2));
+        assert_eq!(2, editions.len());
+        assert_eq!(3, editions[0].others[0].id);
+        assert_eq!(Detection::Hash, Detection::from("HASH"));
+        assert_eq!(Detection::Off, Detection::from("off"));
+    }
+}
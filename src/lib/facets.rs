@@ -0,0 +1,389 @@
+//! The facets of the book lists selected by the query parameters.
+//!
+//! The facets narrow or reorder the current list without changing its route:
+//!  * `?lang=uk` - only the books (authors, series) in Ukrainian
+//!  * `?lang=all` - all languages regardless of the user's default
+//!  * no `lang` - the default language from the user's settings
+//!  * `?sort=title|added|serie|size|popular` - the order of the books
+//!  * `?fresh=1` - only the books which were not downloaded yet
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::future::{ready, Ready};
+
+/// The link relation of the OPDS facets
+pub const FACET_REL: &str = "http://opds-spec.org/facet";
+/// The value of `lang` selecting all languages
+pub const ALL_LANGS: &str = "all";
+const MAX_LANG_LEN: usize = 16;
+
+/// The language selected for the list
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Lang {
+    /// Not selected, the user's default is used
+    #[default]
+    Default,
+    All,
+    Only(String),
+}
+impl From<&str> for Lang {
+    fn from(value: &str) -> Self {
+        let value = value.trim().to_lowercase();
+        let valid = value.len() <= MAX_LANG_LEN
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        match value.as_str() {
+            "" | ALL_LANGS => Lang::All,
+            _ if valid => Lang::Only(value),
+            _ => Lang::Default,
+        }
+    }
+}
+
+/// The order of the books in the list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sort {
+    /// The order of the route
+    #[default]
+    Default,
+    Title,
+    /// The newest first
+    Added,
+    /// The books of the series by their numbers, then the books without series
+    Serie,
+    /// The smallest first
+    Size,
+    /// The most downloaded first
+    Popular,
+}
+impl From<&str> for Sort {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "title" => Sort::Title,
+            "added" => Sort::Added,
+            "serie" => Sort::Serie,
+            "size" => Sort::Size,
+            "popular" => Sort::Popular,
+            _ => Sort::Default,
+        }
+    }
+}
+
+/// The values of the book the lists are sorted by
+#[derive(Debug)]
+pub struct SortKey<'a> {
+    pub title: &'a str,
+    /// The date when the book was added as `YYYY-MM-DD`
+    pub added: &'a str,
+    /// The name of the serie and the number of the book in it
+    pub serie: Option<(&'a str, u32)>,
+    pub size: u32,
+    /// The number of downloads
+    pub downloads: u32,
+}
+
+impl Sort {
+    /// The orders which are advertised as the facets
+    pub const ALL: [Sort; 5] = [
+        Sort::Title,
+        Sort::Added,
+        Sort::Serie,
+        Sort::Size,
+        Sort::Popular,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sort::Default => "",
+            Sort::Title => "title",
+            Sort::Added => "added",
+            Sort::Serie => "serie",
+            Sort::Size => "size",
+            Sort::Popular => "popular",
+        }
+    }
+
+    /// Sort the items by their keys, the items of the same key are ordered by the title.
+    /// The order is kept if the sort is not selected.
+    pub fn sort<T, F>(&self, items: &mut [T], key: F)
+    where
+        F: for<'a> Fn(&'a T) -> SortKey<'a>,
+    {
+        if *self == Sort::Default {
+            return;
+        }
+        items.sort_by(|a, b| self.compare(&key(a), &key(b)));
+    }
+
+    fn compare(&self, a: &SortKey, b: &SortKey) -> Ordering {
+        let order = match self {
+            Sort::Default | Sort::Title => Ordering::Equal,
+            Sort::Added => b.added.cmp(a.added),
+            // the books without series are the last ones
+            Sort::Serie => (a.serie.is_none(), a.serie).cmp(&(b.serie.is_none(), b.serie)),
+            Sort::Size => a.size.cmp(&b.size),
+            Sort::Popular => b.downloads.cmp(&a.downloads),
+        };
+        let lowercase = |title: &str| {
+            title
+                .chars()
+                .flat_map(char::to_lowercase)
+                .collect::<Vec<_>>()
+        };
+        order.then_with(|| lowercase(a.title).cmp(&lowercase(b.title)))
+    }
+}
+
+/// The facets of the requested list
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Facets {
+    /// The path of the list without the query
+    pub path: String,
+    /// The language as it is requested
+    pub lang: Lang,
+    /// The user's default language
+    pub default: Option<String>,
+    pub sort: Sort,
+    /// Only the books which were not downloaded yet
+    pub fresh: bool,
+}
+impl Facets {
+    pub fn new<T: Into<String>>(path: T, query: &str) -> Self {
+        let params = web::Query::<HashMap<String, String>>::from_query(query)
+            .map(|query| query.into_inner())
+            .unwrap_or_default();
+        Facets {
+            path: path.into(),
+            lang: params
+                .get("lang")
+                .map(|lang| Lang::from(lang.as_str()))
+                .unwrap_or_default(),
+            default: None,
+            sort: params
+                .get("sort")
+                .map(|sort| Sort::from(sort.as_str()))
+                .unwrap_or_default(),
+            fresh: params.get("fresh").is_some_and(|fresh| fresh == "1"),
+        }
+    }
+
+    /// Returns the facets with the order of the list if another order is not requested
+    pub fn sort_by_default(mut self, sort: Sort) -> Self {
+        if self.sort == Sort::Default {
+            self.sort = sort;
+        }
+        self
+    }
+
+    /// Returns the facets with the user's default language
+    pub fn resolve(mut self, default: Option<&str>) -> Self {
+        self.default = match default.map(Lang::from) {
+            Some(Lang::Only(lang)) => Some(lang),
+            _ => None,
+        };
+        self
+    }
+
+    /// Returns the selected or the default language, None for all languages
+    pub fn lang(&self) -> Option<&str> {
+        match &self.lang {
+            Lang::Default => self.default.as_deref(),
+            Lang::All => None,
+            Lang::Only(lang) => Some(lang),
+        }
+    }
+
+    /// Returns the query of the requested facets to keep them in the links like `?lang=uk`
+    pub fn query(&self) -> String {
+        encode(&self.lang, self.sort, self.fresh)
+    }
+
+    /// Returns the link to the same list in the language, None for all languages
+    pub fn with_lang(&self, lang: Option<&str>) -> String {
+        let lang = match lang {
+            Some(lang) => Lang::Only(String::from(lang)),
+            None => Lang::All,
+        };
+        format!("{}{}", self.path, encode(&lang, self.sort, self.fresh))
+    }
+
+    /// Returns the link to the same list in the order
+    pub fn with_sort(&self, sort: Sort) -> String {
+        format!("{}{}", self.path, encode(&self.lang, sort, self.fresh))
+    }
+
+    /// Returns the link to the same list of all books or only not downloaded ones
+    pub fn with_fresh(&self, fresh: bool) -> String {
+        format!("{}{}", self.path, encode(&self.lang, self.sort, fresh))
+    }
+}
+
+/// Returns the query like `?lang=uk&sort=added` skipping the facets which are not requested
+fn encode(lang: &Lang, sort: Sort, fresh: bool) -> String {
+    let mut params = Vec::new();
+    match lang {
+        Lang::Default => {}
+        Lang::All => params.push(format!("lang={ALL_LANGS}")),
+        Lang::Only(lang) => {
+            let encoded = utf8_percent_encode(lang, NON_ALPHANUMERIC);
+            params.push(format!("lang={encoded}"));
+        }
+    }
+    if sort != Sort::Default {
+        params.push(format!("sort={}", sort.as_str()));
+    }
+    if fresh {
+        params.push(String::from("fresh=1"));
+    }
+    match params.is_empty() {
+        true => String::new(),
+        false => format!("?{}", params.join("&")),
+    }
+}
+
+impl FromRequest for Facets {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Facets::new(req.path(), req.query_string())))
+    }
+}
+
+/// Returns the languages with the number of items in each one, the most frequent first.
+/// The item may be counted in several languages, e.g. the author of translated books.
+pub fn count_langs<'a, I>(items: I) -> Vec<(String, u32)>
+where
+    I: IntoIterator<Item = &'a [String]>,
+{
+    let mut counts = BTreeMap::new();
+    for langs in items {
+        let mut langs = langs
+            .iter()
+            .map(|lang| lang.to_lowercase())
+            .collect::<Vec<_>>();
+        langs.sort();
+        langs.dedup();
+        for lang in langs {
+            *counts.entry(lang).or_insert(0) += 1;
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts
+}
+
+/// Returns true if one of the languages of the item is the selected one
+pub fn has_lang(langs: &[String], lang: Option<&str>) -> bool {
+    match lang {
+        Some(lang) => langs.iter().any(|l| l.eq_ignore_ascii_case(lang)),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lang() {
+        let facets = Facets::new("/opds/books/serie/id/1", "lang=UK");
+        assert_eq!(Lang::Only(String::from("uk")), facets.lang);
+        assert_eq!(Some("uk"), facets.clone().resolve(Some("ru")).lang());
+        assert_eq!(
+            "/opds/books/serie/id/1?lang=ru",
+            facets.with_lang(Some("ru"))
+        );
+        assert_eq!("/opds/books/serie/id/1?lang=all", facets.with_lang(None));
+
+        let facets = Facets::new("/opds", "lang=all");
+        assert_eq!("?lang=all", facets.query());
+        assert_eq!(None, facets.resolve(Some("ru")).lang());
+        let facets = Facets::new("/opds", "");
+        assert_eq!(Some("ru"), facets.clone().resolve(Some("ru")).lang());
+        assert_eq!(None, facets.resolve(None).lang());
+        let facets = Facets::new("/opds", "lang=%22%3E");
+        assert_eq!(Lang::Default, facets.lang);
+    }
+
+    #[test]
+    fn test_sort() {
+        let facets = Facets::new("/opds/books/serie/id/1", "lang=uk&sort=added");
+        assert_eq!(Sort::Added, facets.sort);
+        assert_eq!("?lang=uk&sort=added", facets.query());
+        assert_eq!(
+            "/opds/books/serie/id/1?lang=uk&sort=size",
+            facets.with_sort(Sort::Size)
+        );
+        assert_eq!(
+            "/opds/books/serie/id/1?lang=all&sort=added",
+            facets.with_lang(None)
+        );
+        assert_eq!(Sort::Added, facets.sort_by_default(Sort::Serie).sort);
+        let facets = Facets::new("/opds", "sort=unknown");
+        assert_eq!(Sort::Serie, facets.sort_by_default(Sort::Serie).sort);
+        let facets = Facets::new("/opds/random", "fresh=1&sort=size");
+        assert!(facets.fresh);
+        assert_eq!(
+            "/opds/random?lang=all&sort=size&fresh=1",
+            facets.with_lang(None)
+        );
+        assert_eq!("/opds/random?sort=size", facets.with_fresh(false));
+
+        // (title, added, serie, size, downloads)
+        type Book = (
+            &'static str,
+            &'static str,
+            Option<(&'static str, u32)>,
+            u32,
+            u32,
+        );
+        fn key(book: &Book) -> SortKey<'_> {
+            SortKey {
+                title: book.0,
+                added: book.1,
+                serie: book.2,
+                size: book.3,
+                downloads: book.4,
+            }
+        }
+        fn titles(books: &[Book]) -> Vec<&str> {
+            books.iter().map(|book| book.0).collect()
+        }
+        let mut books: Vec<Book> = vec![
+            ("b", "2024-01-02", Some(("s", 2)), 300, 0),
+            ("C", "2024-01-01", None, 100, 5),
+            ("a", "2024-01-02", Some(("s", 1)), 200, 1),
+        ];
+        Sort::Default.sort(&mut books, key);
+        assert_eq!(vec!["b", "C", "a"], titles(&books));
+        Sort::Title.sort(&mut books, key);
+        assert_eq!(vec!["a", "b", "C"], titles(&books));
+        Sort::Added.sort(&mut books, key);
+        assert_eq!(vec!["a", "b", "C"], titles(&books));
+        Sort::Serie.sort(&mut books, key);
+        assert_eq!(vec!["a", "b", "C"], titles(&books));
+        Sort::Size.sort(&mut books, key);
+        assert_eq!(vec!["C", "a", "b"], titles(&books));
+        Sort::Popular.sort(&mut books, key);
+        assert_eq!(vec!["C", "a", "b"], titles(&books));
+    }
+
+    #[test]
+    fn test_count_langs() {
+        let items = [
+            vec![String::from("ru")],
+            vec![String::from("ru"), String::from("uk"), String::from("RU")],
+            vec![String::from("en")],
+            vec![String::from("ru")],
+        ];
+        let counts = count_langs(items.iter().map(Vec::as_slice));
+        assert_eq!((String::from("ru"), 3), counts[0]);
+        assert_eq!(3, counts.len());
+        assert!(has_lang(&items[1], Some("uk")));
+        assert!(!has_lang(&items[2], Some("uk")));
+        assert!(has_lang(&items[2], None));
+    }
+}
@@ -0,0 +1,268 @@
+use log::warn;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use regex::Regex;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Default, Clone)]
+pub struct Person {
+    pub first_name: String,
+    pub middle_name: String,
+    pub last_name: String,
+    pub nickname: String,
+}
+impl fmt::Display for Person {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = [&self.last_name, &self.first_name, &self.middle_name]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if name.is_empty() {
+            write!(f, "{}", self.nickname)
+        } else {
+            write!(f, "{name}")
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Sequence {
+    pub name: String,
+    pub number: Option<u32>,
+}
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.number {
+            Some(num) => write!(f, "{} #{num}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The metadata from the FB2 `<description>` element
+#[derive(Debug, Default)]
+pub struct Description {
+    pub title: String,
+    pub authors: Vec<Person>,
+    pub translators: Vec<Person>,
+    pub genres: Vec<String>,
+    pub annotation: String,
+    pub date: String,
+    pub lang: String,
+    pub src_lang: String,
+    pub sequences: Vec<Sequence>,
+    pub publisher: String,
+    pub city: String,
+    pub year: String,
+    pub isbn: String,
+}
+
+/// Decode the document to UTF-8 using the encoding from the XML declaration
+pub fn decode(bytes: &[u8]) -> anyhow::Result<String> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(256)]).into_owned();
+    let rx = Regex::new(r#"encoding\s*=\s*["']([A-Za-z0-9_-]+)["']"#)?;
+    let label = rx
+        .captures(&head)
+        .and_then(|caps| caps.get(1))
+        .map_or("utf-8", |m| m.as_str());
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes()).unwrap_or_else(|| {
+        warn!("Unknown encoding '{label}', will use UTF-8");
+        encoding_rs::UTF_8
+    });
+    let (text, _, malformed) = encoding.decode(bytes);
+    if malformed {
+        warn!("The document contains malformed {label} sequences");
+    }
+    Ok(text.into_owned())
+}
+
+/// Returns the unescaped value of the attribute ignoring its namespace prefix
+pub fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// Read the `<description>` of the FB2 file
+pub fn read_description(path: &Path) -> anyhow::Result<Description> {
+    let bytes = fs::read(path)?;
+    parse_description(&decode(&bytes)?)
+}
+
+/// Parse the `<description>` of the FB2 document, the rest of the document is skipped
+pub fn parse_description(xml: &str) -> anyhow::Result<Description> {
+    let mut reader = Reader::from_str(xml);
+    let mut desc = Description::default();
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut person: Option<Person> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                if path.last().is_some_and(|p| p == b"title-info") {
+                    match name.as_slice() {
+                        b"author" | b"translator" => person = Some(Person::default()),
+                        b"sequence" => desc.sequences.push(sequence(&e)),
+                        _ => {}
+                    }
+                }
+                path.push(name);
+            }
+            Event::Empty(e)
+                if e.local_name().as_ref() == b"sequence"
+                    && path.last().is_some_and(|p| p == b"title-info") =>
+            {
+                desc.sequences.push(sequence(&e));
+            }
+            Event::End(e) => {
+                path.pop();
+                match e.local_name().as_ref() {
+                    b"description" => break,
+                    b"author" | b"translator"
+                        if path.last().is_some_and(|p| p == b"title-info") =>
+                    {
+                        if let Some(person) = person.take() {
+                            if e.local_name().as_ref() == b"author" {
+                                desc.authors.push(person);
+                            } else {
+                                desc.translators.push(person);
+                            }
+                        }
+                    }
+                    b"p" if path.iter().any(|p| p == b"annotation") => {
+                        desc.annotation.push('\n');
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(e) => {
+                let raw = e.unescape()?;
+                let section = path.get(2).map(|p| p.as_slice()).unwrap_or_default();
+                let element = path.last().map(|p| p.as_slice()).unwrap_or_default();
+                if section == b"title-info" && path.iter().any(|p| p == b"annotation") {
+                    desc.annotation.push_str(&raw);
+                    continue;
+                }
+                let text = raw.trim();
+                if text.is_empty() {
+                    continue;
+                }
+                if let Some(person) = person.as_mut() {
+                    match element {
+                        b"first-name" => person.first_name = text.into(),
+                        b"middle-name" => person.middle_name = text.into(),
+                        b"last-name" => person.last_name = text.into(),
+                        b"nickname" => person.nickname = text.into(),
+                        _ => {}
+                    }
+                    continue;
+                }
+                match (section, element) {
+                    (b"title-info", b"book-title") => desc.title = text.into(),
+                    (b"title-info", b"genre") => desc.genres.push(text.into()),
+                    (b"title-info", b"date") => desc.date = text.into(),
+                    (b"title-info", b"lang") => desc.lang = text.into(),
+                    (b"title-info", b"src-lang") => desc.src_lang = text.into(),
+                    (b"publish-info", b"publisher") => desc.publisher = text.into(),
+                    (b"publish-info", b"city") => desc.city = text.into(),
+                    (b"publish-info", b"year") => desc.year = text.into(),
+                    (b"publish-info", b"isbn") => desc.isbn = text.into(),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    desc.annotation = desc
+        .annotation
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(desc)
+}
+
+fn sequence(e: &BytesStart) -> Sequence {
+    Sequence {
+        name: attribute(e, b"name").unwrap_or_default(),
+        number: attribute(e, b"number").and_then(|n| n.trim().parse().ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fb2(encoding: &str, title_info: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="{encoding}"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0">
+<description>
+<title-info>
+<genre>sf</genre>
+{title_info}
+<book-title>Трудно быть богом</book-title>
+<annotation><p>Первый   абзац.</p><p>Второй.</p></annotation>
+<lang>ru</lang>
+</title-info>
+<publish-info><publisher>Мир</publisher><year>1964</year><isbn>5-03-001234-5</isbn></publish-info>
+</description>
+<body><section><p>Текст</p></section></body>
+</FictionBook>"#
+        )
+    }
+
+    #[test]
+    fn test_utf8() {
+        let title_info = r#"
+<author><first-name>Аркадий</first-name><middle-name>Натанович</middle-name><last-name>Стругацкий</last-name></author>
+<author><first-name>Борис</first-name><last-name>Стругацкий</last-name></author>
+<author><nickname>Братья</nickname></author>
+<translator><first-name>Ivan</first-name><last-name>Petrov</last-name></translator>
+<sequence name="Мир Полудня" number=" 3 "/>"#;
+        let xml = decode(fb2("utf-8", title_info).as_bytes()).unwrap();
+        let desc = parse_description(&xml).unwrap();
+        assert_eq!("Трудно быть богом", desc.title);
+        let authors = desc
+            .authors
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["Стругацкий Аркадий Натанович", "Стругацкий Борис", "Братья"],
+            authors
+        );
+        assert_eq!("Petrov Ivan", desc.translators[0].to_string());
+        assert_eq!(vec!["sf"], desc.genres);
+        assert_eq!("Первый абзац.\nВторой.", desc.annotation);
+        assert_eq!("Мир Полудня #3", desc.sequences[0].to_string());
+        assert_eq!(
+            ("ru", "Мир", "1964"),
+            (&*desc.lang, &*desc.publisher, &*desc.year)
+        );
+        assert_eq!("5-03-001234-5", desc.isbn);
+    }
+
+    #[test]
+    fn test_windows_1251() {
+        let title_info = "<author><last-name>Лем</last-name></author>";
+        let xml = fb2("windows-1251", title_info);
+        let (bytes, _, _) = encoding_rs::WINDOWS_1251.encode(&xml);
+        let desc = parse_description(&decode(&bytes).unwrap()).unwrap();
+        assert_eq!("Трудно быть богом", desc.title);
+        assert_eq!("Лем", desc.authors[0].to_string());
+        // the sequence is optional
+        assert!(desc.sequences.is_empty());
+        assert!(desc.translators.is_empty());
+    }
+}
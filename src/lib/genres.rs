@@ -0,0 +1,194 @@
+//! The FB2 genre codes used by lib.rus.ec with their names and meta genres
+
+const SF: &str = "Фантастика";
+const DETECTIVE: &str = "Детективы и Триллеры";
+const PROSE: &str = "Проза";
+const LOVE: &str = "Любовные романы";
+const ADVENTURE: &str = "Приключения";
+const CHILDREN: &str = "Детское";
+const POETRY: &str = "Поэзия, Драматургия";
+const ANTIQUE: &str = "Старинное";
+const SCIENCE: &str = "Наука, Образование";
+const COMPUTERS: &str = "Компьютеры и Интернет";
+const REFERENCE: &str = "Справочная литература";
+const NONFICTION: &str = "Документальная литература";
+const RELIGION: &str = "Религия и духовность";
+const HUMOR: &str = "Юмор";
+const HOME: &str = "Дом и семья";
+const TECH: &str = "Техника";
+const BUSINESS: &str = "Деловая литература";
+const OTHER: &str = "Прочее";
+
+const GENRES: &[(&str, &str, &str)] = &[
+    ("sf_history", "Альтернативная история", SF),
+    ("sf_action", "Боевая фантастика", SF),
+    ("sf_epic", "Эпическая фантастика", SF),
+    ("sf_heroic", "Героическая фантастика", SF),
+    ("sf_detective", "Детективная фантастика", SF),
+    ("sf_cyberpunk", "Киберпанк", SF),
+    ("sf_space", "Космическая фантастика", SF),
+    ("sf_social", "Социально-психологическая фантастика", SF),
+    ("sf_horror", "Ужасы и Мистика", SF),
+    ("sf_humor", "Юмористическая фантастика", SF),
+    ("sf_fantasy", "Фэнтези", SF),
+    ("sf_fantasy_city", "Городское фэнтези", SF),
+    ("sf_postapocalyptic", "Постапокалипсис", SF),
+    ("sf_stimpank", "Стимпанк", SF),
+    ("sf_litrpg", "ЛитРПГ", SF),
+    ("popadanec", "Попаданцы", SF),
+    ("sf", "Научная фантастика", SF),
+    ("det_classic", "Классический детектив", DETECTIVE),
+    ("det_police", "Полицейский детектив", DETECTIVE),
+    ("det_action", "Боевик", DETECTIVE),
+    ("det_irony", "Иронический детектив", DETECTIVE),
+    ("det_history", "Исторический детектив", DETECTIVE),
+    ("det_espionage", "Шпионский детектив", DETECTIVE),
+    ("det_crime", "Криминальный детектив", DETECTIVE),
+    ("det_political", "Политический детектив", DETECTIVE),
+    ("det_maniac", "Маньяки", DETECTIVE),
+    ("det_hard", "Крутой детектив", DETECTIVE),
+    ("thriller", "Триллер", DETECTIVE),
+    ("detective", "Детектив", DETECTIVE),
+    ("prose_classic", "Классическая проза", PROSE),
+    ("prose_history", "Историческая проза", PROSE),
+    ("prose_contemporary", "Современная проза", PROSE),
+    ("prose_counter", "Контркультура", PROSE),
+    ("prose_rus_classic", "Русская классическая проза", PROSE),
+    ("prose_su_classics", "Советская классическая проза", PROSE),
+    ("prose_military", "Проза о войне", PROSE),
+    ("aphorisms", "Афоризмы", PROSE),
+    ("essay", "Эссе, очерк, этюд, набросок", PROSE),
+    ("story", "Рассказ", PROSE),
+    ("prose", "Проза", PROSE),
+    ("love_contemporary", "Современные любовные романы", LOVE),
+    ("love_history", "Исторические любовные романы", LOVE),
+    ("love_detective", "Остросюжетные любовные романы", LOVE),
+    ("love_short", "Короткие любовные романы", LOVE),
+    ("love_erotica", "Эротика", LOVE),
+    ("love_sf", "Любовное фэнтези", LOVE),
+    ("love", "Любовные романы", LOVE),
+    ("adv_western", "Вестерн", ADVENTURE),
+    ("adv_history", "Исторические приключения", ADVENTURE),
+    ("adv_indian", "Приключения про индейцев", ADVENTURE),
+    ("adv_maritime", "Морские приключения", ADVENTURE),
+    ("adv_geo", "Путешествия и география", ADVENTURE),
+    ("adv_animal", "Природа и животные", ADVENTURE),
+    ("adventure", "Приключения", ADVENTURE),
+    ("child_tale", "Сказка", CHILDREN),
+    ("child_verse", "Детские стихи", CHILDREN),
+    ("child_prose", "Детская проза", CHILDREN),
+    ("child_sf", "Детская фантастика", CHILDREN),
+    ("child_det", "Детские остросюжетные", CHILDREN),
+    ("child_adv", "Детские приключения", CHILDREN),
+    (
+        "child_education",
+        "Детская образовательная литература",
+        CHILDREN,
+    ),
+    ("children", "Детская литература", CHILDREN),
+    ("poetry", "Поэзия", POETRY),
+    ("dramaturgy", "Драматургия", POETRY),
+    ("antique_ant", "Античная литература", ANTIQUE),
+    (
+        "antique_european",
+        "Европейская старинная литература",
+        ANTIQUE,
+    ),
+    ("antique_russian", "Древнерусская литература", ANTIQUE),
+    ("antique_east", "Древневосточная литература", ANTIQUE),
+    ("antique_myths", "Мифы. Легенды. Эпос", ANTIQUE),
+    ("antique", "Старинная литература", ANTIQUE),
+    ("sci_history", "История", SCIENCE),
+    ("sci_psychology", "Психология", SCIENCE),
+    ("sci_culture", "Культурология", SCIENCE),
+    ("sci_religion", "Религиоведение", SCIENCE),
+    ("sci_philosophy", "Философия", SCIENCE),
+    ("sci_politics", "Политика", SCIENCE),
+    ("sci_business", "Деловая литература", SCIENCE),
+    ("sci_juris", "Юриспруденция", SCIENCE),
+    ("sci_linguistic", "Языкознание", SCIENCE),
+    ("sci_medicine", "Медицина", SCIENCE),
+    ("sci_phys", "Физика", SCIENCE),
+    ("sci_math", "Математика", SCIENCE),
+    ("sci_chem", "Химия", SCIENCE),
+    ("sci_biology", "Биология", SCIENCE),
+    ("sci_tech", "Технические науки", SCIENCE),
+    ("sci_economy", "Экономика", SCIENCE),
+    ("sci_social_studies", "Обществознание", SCIENCE),
+    ("sci_pedagogy", "Педагогика", SCIENCE),
+    ("science", "Научная литература", SCIENCE),
+    ("comp_www", "Интернет", COMPUTERS),
+    ("comp_programming", "Программирование", COMPUTERS),
+    ("comp_hard", "Компьютерное железо", COMPUTERS),
+    ("comp_soft", "Программы", COMPUTERS),
+    ("comp_db", "Базы данных", COMPUTERS),
+    ("comp_osnet", "ОС и Сети", COMPUTERS),
+    ("computers", "Компьютеры", COMPUTERS),
+    ("ref_encyc", "Энциклопедии", REFERENCE),
+    ("ref_dict", "Словари", REFERENCE),
+    ("ref_ref", "Справочники", REFERENCE),
+    ("ref_guide", "Руководства", REFERENCE),
+    ("reference", "Справочная литература", REFERENCE),
+    ("nonf_biography", "Биографии и Мемуары", NONFICTION),
+    ("nonf_publicism", "Публицистика", NONFICTION),
+    ("nonf_criticism", "Критика", NONFICTION),
+    ("nonf_military", "Военная документалистика", NONFICTION),
+    ("design", "Искусство и Дизайн", NONFICTION),
+    ("nonfiction", "Документальная литература", NONFICTION),
+    ("religion_rel", "Религия", RELIGION),
+    ("religion_esoterics", "Эзотерика", RELIGION),
+    ("religion_self", "Самосовершенствование", RELIGION),
+    ("religion", "Религиозная литература", RELIGION),
+    ("humor_anecdote", "Анекдоты", HUMOR),
+    ("humor_prose", "Юмористическая проза", HUMOR),
+    ("humor_verse", "Юмористические стихи", HUMOR),
+    ("humor", "Юмор", HUMOR),
+    ("home_cooking", "Кулинария", HOME),
+    ("home_pets", "Домашние животные", HOME),
+    ("home_crafts", "Хобби и ремесла", HOME),
+    ("home_entertain", "Развлечения", HOME),
+    ("home_health", "Здоровье", HOME),
+    ("home_garden", "Сад и огород", HOME),
+    ("home_diy", "Сделай сам", HOME),
+    ("home_sport", "Спорт", HOME),
+    ("home_sex", "Эротика, Секс", HOME),
+    ("home", "Домоводство", HOME),
+    ("auto_regulations", "Автомобили и ПДД", TECH),
+    (
+        "military_weapon",
+        "Военное дело, военная техника и вооружение",
+        TECH,
+    ),
+    ("equ_history", "История техники", TECH),
+    ("economics", "Экономика", BUSINESS),
+    ("management", "Управление, подбор персонала", BUSINESS),
+    ("marketing", "Маркетинг, PR, реклама", BUSINESS),
+    ("banking", "Банковское дело", BUSINESS),
+    ("org_behavior", "Корпоративная культура", BUSINESS),
+    ("popular_business", "Карьера, кадры", BUSINESS),
+    ("small_business", "Малый бизнес", BUSINESS),
+    ("job_hunting", "Поиск работы, карьера", BUSINESS),
+];
+
+/// Returns the name and the meta genre of the FB2 genre code
+pub fn describe(code: &str) -> (&str, &'static str) {
+    GENRES
+        .iter()
+        .find(|(c, _, _)| *c == code)
+        .map_or((code, OTHER), |(_, name, meta)| (*name, *meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(("Киберпанк", SF), describe("sf_cyberpunk"));
+        assert_eq!(("sf_unknown", OTHER), describe("sf_unknown"));
+        let mut codes = GENRES.iter().map(|(code, _, _)| *code).collect::<Vec<_>>();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(GENRES.len(), codes.len());
+    }
+}
@@ -0,0 +1,188 @@
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{FromRequest, HttpRequest};
+use lazy_static::lazy_static;
+
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+
+const MONTHS: [&str; 12] = [
+    "month.1", "month.2", "month.3", "month.4", "month.5", "month.6", "month.7", "month.8",
+    "month.9", "month.10", "month.11", "month.12",
+];
+
+/// The languages of the books which names are translated
+const LANGUAGES: [(&str, &str); 12] = [
+    ("ru", "lang.ru"),
+    ("uk", "lang.uk"),
+    ("be", "lang.be"),
+    ("en", "lang.en"),
+    ("de", "lang.de"),
+    ("fr", "lang.fr"),
+    ("es", "lang.es"),
+    ("it", "lang.it"),
+    ("pl", "lang.pl"),
+    ("bg", "lang.bg"),
+    ("cs", "lang.cs"),
+    ("la", "lang.la"),
+];
+
+type Catalogue = HashMap<&'static str, &'static str>;
+
+lazy_static! {
+    static ref RU: Catalogue = parse(include_str!("locales/ru.txt"));
+    static ref UK: Catalogue = parse(include_str!("locales/uk.txt"));
+    static ref EN: Catalogue = parse(include_str!("locales/en.txt"));
+}
+
+fn parse(content: &'static str) -> Catalogue {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    Ru,
+    Uk,
+    En,
+}
+impl Locale {
+    /// Returns the locale by the language tag like `ru`, `uk-UA` or `en_US.UTF-8`
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let lang = tag
+            .trim()
+            .split(['-', '_', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match lang.as_str() {
+            "ru" => Some(Locale::Ru),
+            "uk" | "ua" => Some(Locale::Uk),
+            "en" => Some(Locale::En),
+            _ => None,
+        }
+    }
+
+    /// Returns the most preferred supported locale from the `Accept-Language` header value
+    pub fn from_accept_language(value: &str) -> Option<Self> {
+        let mut candidates = value
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let locale = Locale::from_tag(parts.next()?)?;
+                let quality = parts
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .filter_map(|q| q.parse::<f32>().ok())
+                    .next()
+                    .unwrap_or(1.0);
+                Some((locale, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.first().map(|(locale, _)| *locale)
+    }
+
+    fn catalogue(&self) -> &'static Catalogue {
+        match self {
+            Locale::Ru => &RU,
+            Locale::Uk => &UK,
+            Locale::En => &EN,
+        }
+    }
+
+    /// Returns the message by the key, falls back to Russian and then to the key itself
+    pub fn tr(&self, key: &'static str) -> &'static str {
+        self.catalogue()
+            .get(key)
+            .or_else(|| RU.get(key))
+            .copied()
+            .unwrap_or(key)
+    }
+
+    /// Returns the message by the key with `{name}` placeholders replaced by the arguments
+    pub fn tr_args(&self, key: &'static str, args: &[(&str, String)]) -> String {
+        let mut message = String::from(self.tr(key));
+        for (name, value) in args {
+            message = message.replace(&format!("{{{name}}}"), value);
+        }
+        message
+    }
+
+    /// Returns the name of the month, where 1 is January
+    pub fn month(&self, month: u32) -> &'static str {
+        let idx = (month.clamp(1, 12) - 1) as usize;
+        self.tr(MONTHS[idx])
+    }
+
+    /// Returns the name of the language by the code like `uk`, the code itself if it is unknown
+    pub fn language<'a>(&self, code: &'a str) -> &'a str {
+        LANGUAGES
+            .iter()
+            .find(|(lang, _)| code.eq_ignore_ascii_case(lang))
+            .map_or(code, |(_, key)| self.tr(key))
+    }
+}
+
+/// The locale is taken from the `Accept-Language` header or from the configured default one
+impl FromRequest for Locale {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let locale = req
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Locale::from_accept_language)
+            .or_else(|| req.app_data::<Locale>().copied())
+            .unwrap_or_default();
+        ready(Ok(locale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_language() {
+        assert_eq!(
+            Some(Locale::En),
+            Locale::from_accept_language("en-US,en;q=0.9")
+        );
+        assert_eq!(
+            Some(Locale::Uk),
+            Locale::from_accept_language("de;q=1.0, ru;q=0.5, uk;q=0.8")
+        );
+        assert_eq!(None, Locale::from_accept_language("de, fr;q=0.5"));
+        assert_eq!(None, Locale::from_accept_language("ru;q=0"));
+    }
+
+    #[test]
+    fn test_catalogues_are_complete() {
+        for (name, catalogue) in [("uk", &*UK), ("en", &*EN)] {
+            for key in RU.keys() {
+                assert!(catalogue.contains_key(key), "'{key}' is missing in {name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_tr_args() {
+        let message = Locale::En.tr_args(
+            "genre.month",
+            &[("month", "May".into()), ("year", "2024".into())],
+        );
+        assert_eq!("Books for May 2024", message);
+        assert_eq!("unknown.key", Locale::Uk.tr("unknown.key"));
+        assert_eq!("Ukrainian", Locale::En.language("UK"));
+        assert_eq!("eo", Locale::En.language("eo"));
+    }
+}
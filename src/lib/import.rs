@@ -0,0 +1,630 @@
+use log::{debug, error, info, warn};
+use regex::Regex;
+use rusqlite::Connection;
+
+use crate::fb2::{self, Description};
+use crate::genres;
+use crate::inpx::{self, Record, Skip};
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The catalog schema, it has to provide the tables of `catalog::TABLES`.
+///
+/// The upstream `opds_api` crate does not publish its schema, so the tables here follow the
+/// layout its queries were written against (the lib.rus.ec catalog converted by the original
+/// tools). `test_opds_api` imports an index and runs the `OpdsApi` queries on the result to
+/// keep both sides in agreement.
+pub const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS titles(id INTEGER PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+    CREATE TABLE IF NOT EXISTS series(id INTEGER PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+    CREATE TABLE IF NOT EXISTS first_names(id INTEGER PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+    CREATE TABLE IF NOT EXISTS middle_names(id INTEGER PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+    CREATE TABLE IF NOT EXISTS last_names(id INTEGER PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+    CREATE TABLE IF NOT EXISTS genres(
+        id      INTEGER PRIMARY KEY,
+        code    TEXT NOT NULL UNIQUE,
+        value   TEXT NOT NULL,
+        meta    TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS books(
+        book_id     INTEGER PRIMARY KEY,
+        title_id    INTEGER NOT NULL,
+        serie_id    INTEGER,
+        serie_num   INTEGER,
+        size        INTEGER,
+        lang        TEXT,
+        added       TEXT,
+        deleted     INTEGER NOT NULL DEFAULT 0);
+    CREATE TABLE IF NOT EXISTS authors_map(
+        book_id         INTEGER NOT NULL,
+        first_name_id   INTEGER NOT NULL,
+        middle_name_id  INTEGER NOT NULL,
+        last_name_id    INTEGER NOT NULL,
+        UNIQUE(book_id, first_name_id, middle_name_id, last_name_id) ON CONFLICT IGNORE);
+    CREATE TABLE IF NOT EXISTS genres_map(
+        book_id     INTEGER NOT NULL,
+        genre_id    INTEGER NOT NULL,
+        UNIQUE(book_id, genre_id) ON CONFLICT IGNORE);
+    CREATE TABLE IF NOT EXISTS archives(
+        name        TEXT NOT NULL PRIMARY KEY,
+        imported    DATETIME DEFAULT CURRENT_TIMESTAMP);
+    CREATE INDEX IF NOT EXISTS authors_map_names
+        ON authors_map(first_name_id, middle_name_id, last_name_id);
+    CREATE INDEX IF NOT EXISTS genres_map_genre ON genres_map(genre_id);
+    CREATE INDEX IF NOT EXISTS books_serie ON books(serie_id);
+    CREATE INDEX IF NOT EXISTS books_added ON books(added);
+"#;
+
+/// The size in the zip header is not trusted for the larger books
+const MAX_PREALLOCATED: u64 = 16 * 1024 * 1024;
+
+/// The result of the import
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub archives: usize,
+    pub books: usize,
+    pub skipped: BTreeMap<Skip, usize>,
+}
+impl Summary {
+    pub fn skip(&mut self, reason: Skip) {
+        *self.skipped.entry(reason).or_default() += 1;
+    }
+}
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} archives, {} books", self.archives, self.books)?;
+        for (reason, count) in self.skipped.iter() {
+            write!(f, ", {count} skipped as {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Importer {
+    conn: Connection,
+    ids: HashMap<(&'static str, String), u32>,
+}
+impl Importer {
+    /// Create Importer instance, the catalog schema is created if it does not exist
+    pub fn new(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Importer {
+            conn,
+            ids: HashMap::new(),
+        })
+    }
+
+    pub fn begin(&self) -> anyhow::Result<()> {
+        Ok(self.conn.execute_batch("BEGIN;")?)
+    }
+
+    pub fn commit(&self) -> anyhow::Result<()> {
+        Ok(self.conn.execute_batch("COMMIT;")?)
+    }
+
+    /// Returns id of the value in the dictionary table, the value is added if needed
+    fn value_id(&mut self, table: &'static str, value: &str) -> anyhow::Result<u32> {
+        let key = (table, String::from(value));
+        if let Some(id) = self.ids.get(&key) {
+            return Ok(*id);
+        }
+        let sql = format!("INSERT OR IGNORE INTO {table}(value) VALUES($1);");
+        self.conn.prepare_cached(&sql)?.execute([value])?;
+        let sql = format!("SELECT id FROM {table} WHERE value = $1;");
+        let id = self
+            .conn
+            .prepare_cached(&sql)?
+            .query_row([value], |row| row.get(0))?;
+        self.ids.insert(key, id);
+        Ok(id)
+    }
+
+    fn genre_id(&mut self, code: &str) -> anyhow::Result<u32> {
+        let key = ("genres", String::from(code));
+        if let Some(id) = self.ids.get(&key) {
+            return Ok(*id);
+        }
+        let (name, meta) = genres::describe(code);
+        let sql = "INSERT OR IGNORE INTO genres(code, value, meta) VALUES($1, $2, $3);";
+        self.conn.prepare_cached(sql)?.execute([code, name, meta])?;
+        let sql = "SELECT id FROM genres WHERE code = $1;";
+        let id = self
+            .conn
+            .prepare_cached(sql)?
+            .query_row([code], |row| row.get(0))?;
+        self.ids.insert(key, id);
+        Ok(id)
+    }
+
+    /// Insert or replace the book with its authors and genres
+    pub fn insert(&mut self, record: &Record) -> anyhow::Result<()> {
+        let title_id = self.value_id("titles", &record.title)?;
+        let serie_id = if record.serie.is_empty() {
+            None
+        } else {
+            Some(self.value_id("series", &record.serie)?)
+        };
+
+        let sql = "INSERT OR REPLACE INTO books VALUES($1, $2, $3, $4, $5, $6, $7, $8);";
+        self.conn.prepare_cached(sql)?.execute((
+            record.id,
+            title_id,
+            serie_id,
+            record.serie_num,
+            record.size,
+            &record.lang,
+            &record.added,
+            record.deleted,
+        ))?;
+
+        let sql = "DELETE FROM authors_map WHERE book_id = $1;";
+        self.conn.prepare_cached(sql)?.execute([record.id])?;
+        for author in record.authors.iter() {
+            let fid = self.value_id("first_names", &author.first_name)?;
+            let mid = self.value_id("middle_names", &author.middle_name)?;
+            let lid = self.value_id("last_names", &author.last_name)?;
+            let sql = "INSERT INTO authors_map VALUES($1, $2, $3, $4);";
+            self.conn
+                .prepare_cached(sql)?
+                .execute([record.id, fid, mid, lid])?;
+        }
+
+        let sql = "DELETE FROM genres_map WHERE book_id = $1;";
+        self.conn.prepare_cached(sql)?.execute([record.id])?;
+        for code in record.genres.iter() {
+            let gid = self.genre_id(code)?;
+            let sql = "INSERT INTO genres_map VALUES($1, $2);";
+            self.conn.prepare_cached(sql)?.execute([record.id, gid])?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the archive was already imported
+    pub fn has_archive(&self, name: &str) -> anyhow::Result<bool> {
+        let sql = "SELECT COUNT(*) FROM archives WHERE name = $1;";
+        let count: u32 = self
+            .conn
+            .prepare_cached(sql)?
+            .query_row([name], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    /// Returns true if the catalog has books with ids in the range,
+    /// used for catalogs which were built without the `archives` table filled
+    pub fn has_books(&self, min: u32, max: u32) -> anyhow::Result<bool> {
+        let sql = "SELECT COUNT(*) FROM books WHERE book_id BETWEEN $1 AND $2;";
+        let count: u32 = self
+            .conn
+            .prepare_cached(sql)?
+            .query_row([min, max], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    /// Mark the archive as imported
+    pub fn add_archive(&self, name: &str) -> anyhow::Result<()> {
+        let sql = "INSERT OR REPLACE INTO archives(name, imported) VALUES($1, datetime('now'));";
+        self.conn.prepare_cached(sql)?.execute([name])?;
+        Ok(())
+    }
+}
+impl TryFrom<&str> for Importer {
+    type Error = anyhow::Error;
+
+    fn try_from(database: &str) -> anyhow::Result<Self> {
+        debug!("database: {database}");
+        let conn = Connection::open(database).inspect_err(|e| error!("{e}"))?;
+        Importer::new(conn)
+    }
+}
+
+/// Import the `.inpx` index into the catalog database, the book archives are expected to be
+/// in the same directory as the index
+pub fn import_inpx(index: &Path, database: &str) -> anyhow::Result<Summary> {
+    let mut importer = Importer::try_from(database)?;
+    import_index(&mut importer, index)
+}
+
+fn import_index(importer: &mut Importer, index: &Path) -> anyhow::Result<Summary> {
+    let library = index.parent().unwrap_or(Path::new("."));
+    let (structure, inps) = inpx::read(index)?;
+    let total = inps.iter().map(|inp| inp.lines.len()).sum::<usize>().max(1);
+    info!("Found {} archives with {total} records", inps.len());
+
+    let mut summary = Summary::default();
+    let mut processed = 0;
+    for inp in inps.iter() {
+        let exists = library.join(format!("{}.zip", inp.archive)).is_file();
+        if !exists {
+            warn!("The archive {}.zip was not found", inp.archive);
+        }
+
+        importer.begin()?;
+        for line in inp.lines.iter().filter(|l| !l.trim().is_empty()) {
+            processed += 1;
+            if !exists {
+                summary.skip(Skip::NoArchive);
+                continue;
+            }
+            match structure.parse(line) {
+                Ok(record) => {
+                    importer.insert(&record)?;
+                    summary.books += 1;
+                }
+                Err(reason) => {
+                    debug!("{}: {reason}: {line}", inp.archive);
+                    summary.skip(reason);
+                }
+            }
+        }
+        if exists {
+            importer.add_archive(&inp.archive)?;
+            summary.archives += 1;
+        }
+        importer.commit()?;
+        info!(
+            "{}: {processed}/{total} records ({}%)",
+            inp.archive,
+            processed * 100 / total
+        );
+    }
+    Ok(summary)
+}
+
+/// The archive with books named as `fb2-MIN-MAX.zip` where MIN and MAX are the range of book ids
+#[derive(Debug)]
+pub struct Archive {
+    pub name: String,
+    pub path: PathBuf,
+    pub min: u32,
+    pub max: u32,
+}
+
+/// Returns the book archives of the library sorted by name
+pub fn archives(library: &Path) -> anyhow::Result<Vec<Archive>> {
+    let rx = Regex::new(r"^(fb2-(\d+)-(\d+))\.zip$")?;
+    let mut archives = Vec::new();
+    for entry in fs::read_dir(library)? {
+        let path = entry?.path();
+        let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+        let Some(caps) = file_name.as_deref().and_then(|n| rx.captures(n)) else {
+            continue;
+        };
+        let (Ok(min), Ok(max)) = (caps[2].parse(), caps[3].parse()) else {
+            continue;
+        };
+        archives.push(Archive {
+            name: caps[1].to_string(),
+            path: path.clone(),
+            min,
+            max,
+        });
+    }
+    archives.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(archives)
+}
+
+/// Convert the FB2 description of the book into the catalog record
+fn record(id: u32, size: u64, desc: Description, added: &str) -> Result<Record, Skip> {
+    let title = desc.title.trim();
+    if title.is_empty() {
+        return Err(Skip::NoTitle);
+    }
+    let serie = desc
+        .sequences
+        .into_iter()
+        .find(|s| !s.name.trim().is_empty());
+    let authors = desc
+        .authors
+        .into_iter()
+        .map(|mut author| {
+            if author.last_name.is_empty() && author.first_name.is_empty() {
+                author.last_name = std::mem::take(&mut author.nickname);
+            }
+            author
+        })
+        .filter(|author| !author.last_name.is_empty() || !author.first_name.is_empty())
+        .collect();
+
+    Ok(Record {
+        id,
+        authors,
+        genres: desc.genres,
+        title: title.to_string(),
+        serie_num: serie.as_ref().and_then(|s| s.number).unwrap_or(0),
+        serie: serie.map(|s| s.name.trim().to_string()).unwrap_or_default(),
+        size,
+        deleted: false,
+        lang: desc.lang.trim().to_lowercase(),
+        added: added.to_string(),
+    })
+}
+
+/// Read descriptions of all FB2 books in the archive and insert them into the catalog
+fn update_archive(
+    importer: &mut Importer,
+    archive: &Archive,
+    added: &str,
+    summary: &mut Summary,
+) -> anyhow::Result<()> {
+    let file = fs::File::open(&archive.path)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let name = file.name().to_string();
+        let Some(stem) = name.strip_suffix(".fb2") else {
+            summary.skip(Skip::NotFb2);
+            continue;
+        };
+        let Ok(id) = stem.parse::<u32>() else {
+            debug!("{}: {name}: unexpected file name", archive.name);
+            summary.skip(Skip::Malformed);
+            continue;
+        };
+
+        let size = file.size();
+        let mut bytes = Vec::with_capacity(size.min(MAX_PREALLOCATED) as usize);
+        file.read_to_end(&mut bytes)?;
+        let desc = match fb2::decode(&bytes).and_then(|xml| fb2::parse_description(&xml)) {
+            Ok(desc) => desc,
+            Err(err) => {
+                warn!("{}: {name}: {err}", archive.name);
+                summary.skip(Skip::Malformed);
+                continue;
+            }
+        };
+        match record(id, size, desc, added) {
+            Ok(record) => {
+                importer.insert(&record)?;
+                summary.books += 1;
+            }
+            Err(reason) => {
+                debug!("{}: {name}: {reason}", archive.name);
+                summary.skip(reason);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Add books from the library archives which are not in the catalog yet,
+/// the books descriptions are read from the FB2 files directly
+pub fn update_library(library: &Path, database: &str) -> anyhow::Result<Summary> {
+    let mut importer = Importer::try_from(database)?;
+    let added = chrono::Local::now().format("%Y-%m-%d").to_string();
+    update_archives(&mut importer, library, &added)
+}
+
+fn update_archives(
+    importer: &mut Importer,
+    library: &Path,
+    added: &str,
+) -> anyhow::Result<Summary> {
+    let mut summary = Summary::default();
+
+    for archive in archives(library)?.iter() {
+        if importer.has_archive(&archive.name)? {
+            continue;
+        }
+        importer.begin()?;
+        if importer.has_books(archive.min, archive.max)? {
+            info!("{}: already in the catalog", archive.name);
+        } else {
+            info!("{}: reading books", archive.name);
+            let books = summary.books;
+            update_archive(importer, archive, added, &mut summary)?;
+            info!("{}: {} books added", archive.name, summary.books - books);
+            summary.archives += 1;
+        }
+        importer.add_archive(&archive.name)?;
+        importer.commit()?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    /// Returns the empty directory for the files of the test
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("opds-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_zip(path: &Path, files: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn rows(importer: &Importer, sql: &str) -> Vec<String> {
+        let mut statement = importer.conn.prepare(sql).unwrap();
+        let rows = statement.query_map([], |row| row.get(0)).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_import_inpx() {
+        let dir = directory("import-inpx");
+        let inp = [
+            "Стругацкий,Аркадий,Натанович:Стругацкий,Борис,Натанович:\x04sf_social:unknown:\x04Пикник на обочине\x04Миры\x043\x04100\x04500\x04100\x040\x04fb2\x042009-02-28\x04RU\x04",
+            "Лем,Станислав,:\x04sf:\x04Солярис\x04\x04\x04101\x04600\x04101\x041\x04fb2\x042009-03-01\x04ru\x04",
+            "Лем,Станислав,:\x04sf:\x04Читать.pdf\x04\x04\x04102\x04600\x04102\x040\x04pdf\x042009-03-01\x04ru\x04",
+        ]
+        .join("\r\n");
+        let missing = "Автор,,:\x04sf:\x04Книга\x04\x04\x04200\x04600\x04200\x040\x04fb2\x042009-03-01\x04ru\x04";
+        write_zip(
+            &dir.join("lib.inpx"),
+            &[
+                ("fb2-000100-000199.inp", inp.as_str()),
+                ("fb2-000200-000299.inp", missing),
+            ],
+        );
+        write_zip(&dir.join("fb2-000100-000199.zip"), &[]);
+
+        let mut importer = Importer::new(Connection::open_in_memory().unwrap()).unwrap();
+        let summary = import_index(&mut importer, &dir.join("lib.inpx")).unwrap();
+        assert_eq!(
+            "1 archives, 2 books, 1 skipped as not FB2 book, 1 skipped as archive not found",
+            summary.to_string()
+        );
+        let sql = r#"
+            SELECT books.book_id || ' ' || titles.value || ' ' || IFNULL(series.value, '-') || ' '
+                || IFNULL(books.serie_num, 0) || ' ' || books.lang || ' ' || books.deleted
+            FROM books JOIN titles ON titles.id = books.title_id
+            LEFT JOIN series ON series.id = books.serie_id
+            ORDER BY books.book_id;
+        "#;
+        assert_eq!(
+            vec!["100 Пикник на обочине Миры 3 ru 0", "101 Солярис - 0 ru 1"],
+            rows(&importer, sql)
+        );
+        let sql = r#"
+            SELECT authors_map.book_id || ' ' || last_names.value || ' ' || first_names.value
+            FROM authors_map
+            JOIN first_names ON first_names.id = authors_map.first_name_id
+            JOIN last_names ON last_names.id = authors_map.last_name_id
+            ORDER BY authors_map.book_id, first_names.value;
+        "#;
+        assert_eq!(
+            vec![
+                "100 Стругацкий Аркадий",
+                "100 Стругацкий Борис",
+                "101 Лем Станислав"
+            ],
+            rows(&importer, sql)
+        );
+        let sql = r#"
+            SELECT genres_map.book_id || ' ' || genres.code || ' ' || genres.value || ' ' || genres.meta
+            FROM genres_map JOIN genres ON genres.id = genres_map.genre_id
+            ORDER BY genres_map.book_id, genres.code;
+        "#;
+        assert_eq!(
+            vec![
+                "100 sf_social Социально-психологическая фантастика Фантастика",
+                "100 unknown unknown Прочее",
+                "101 sf Научная фантастика Фантастика"
+            ],
+            rows(&importer, sql)
+        );
+        assert!(importer.has_archive("fb2-000100-000199").unwrap());
+        assert!(!importer.has_archive("fb2-000200-000299").unwrap());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_update_library() {
+        let dir = directory("update-library");
+        let book = r#"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook><description><title-info>
+<author><nickname>Аноним</nickname></author>
+<book-title> Сказки </book-title><lang>RU</lang>
+<sequence name=" "/><sequence name="Сборники" number="2"/>
+</title-info></description></FictionBook>"#;
+        let untitled =
+            "<FictionBook><description><title-info></title-info></description></FictionBook>";
+        let files = [
+            ("5.fb2", book),
+            ("6.fb2", untitled),
+            ("7.fb2", "<FictionBook><description></title-info>"),
+            ("x.fb2", book),
+            ("readme.txt", ""),
+        ];
+        write_zip(&dir.join("fb2-000001-000010.zip"), &files);
+        write_zip(&dir.join("fb2-000011-000020.zip"), &[("11.fb2", book)]);
+        write_zip(&dir.join("other.zip"), &[("21.fb2", book)]);
+
+        let mut importer = Importer::new(Connection::open_in_memory().unwrap()).unwrap();
+        // the books of the second archive are already in the catalog
+        let record = Record {
+            id: 12,
+            title: String::from("Книга"),
+            ..Default::default()
+        };
+        importer.insert(&record).unwrap();
+        let summary = update_archives(&mut importer, &dir, "2024-05-01").unwrap();
+        assert_eq!(1, summary.archives);
+        assert_eq!(1, summary.books);
+        assert_eq!(Some(&1), summary.skipped.get(&Skip::NoTitle));
+        assert_eq!(Some(&2), summary.skipped.get(&Skip::Malformed));
+        assert_eq!(Some(&1), summary.skipped.get(&Skip::NotFb2));
+
+        let sql = r#"
+            SELECT books.book_id || ' ' || titles.value || ' ' || series.value || ' '
+                || books.serie_num || ' ' || books.lang || ' ' || books.added || ' ' || books.size
+            FROM books JOIN titles ON titles.id = books.title_id
+            JOIN series ON series.id = books.serie_id;
+        "#;
+        let size = book.len();
+        assert_eq!(
+            vec![format!("5 Сказки Сборники 2 ru 2024-05-01 {size}")],
+            rows(&importer, sql)
+        );
+        assert_eq!(
+            vec!["Аноним"],
+            rows(&importer, "SELECT value FROM last_names;")
+        );
+        assert!(importer.has_archive("fb2-000011-000020").unwrap());
+
+        // the imported archives are skipped
+        let summary = update_archives(&mut importer, &dir, "2024-05-02").unwrap();
+        assert_eq!(0, summary.archives);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_opds_api() {
+        let dir = directory("opds-api");
+        let inp = [
+            "Лем,Станислав,:\x04sf:\x04Солярис\x04\x04\x04101\x04600\x04101\x040\x04fb2\x042009-03-01\x04ru\x04",
+            "Лем,Станислав,:\x04sf:\x04Непобедимый\x04\x04\x04102\x04600\x04102\x040\x04fb2\x042009-03-02\x04ru\x04",
+            "Лесков,Николай,Семенович:\x04prose:\x04Левша\x04\x04\x04103\x04600\x04103\x040\x04fb2\x042009-03-03\x04ru\x04",
+        ]
+        .join("\r\n");
+        write_zip(
+            &dir.join("lib.inpx"),
+            &[("fb2-000100-000199.inp", inp.as_str())],
+        );
+        write_zip(&dir.join("fb2-000100-000199.zip"), &[]);
+        let database = dir.join("books.db");
+        let database = database.to_str().unwrap();
+        import_inpx(&dir.join("lib.inpx"), database).unwrap();
+        crate::catalog::CatalogApi::try_from(database).unwrap();
+
+        let api = opds_api::OpdsApi::try_from(database).unwrap();
+        let next = api
+            .authors_next_char_by_prefix(&String::from("Ле"))
+            .unwrap();
+        assert_eq!(vec!["Лем", "Лес"], next);
+        let authors = api.authors_by_last_name(&String::from("Лем")).unwrap();
+        assert_eq!(1, authors.len());
+        let author = &authors[0];
+        assert_eq!("Станислав", author.first_name.value);
+        let (fid, mid, lid) = (
+            author.first_name.id,
+            author.middle_name.id,
+            author.last_name.id,
+        );
+        let mut books = api
+            .books_by_author_ids(fid, mid, lid)
+            .unwrap()
+            .into_iter()
+            .map(|book| book.id)
+            .collect::<Vec<_>>();
+        books.sort();
+        assert_eq!(vec![101, 102], books);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,211 @@
+//! Reader of the `.inpx` catalog index used by lib.rus.ec and Flibusta mirrors.
+//!
+//! The index is a zip archive with `<archive>.inp` files, one per `<archive>.zip` with books.
+//! Each line of `.inp` is a record with fields separated by `\x04`, the order of the fields
+//! is defined by the optional `structure.info` file.
+use log::{debug, info};
+
+use crate::fb2::Person;
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+const DEFAULT_STRUCTURE: &str =
+    "AUTHOR;GENRE;TITLE;SERIES;SERNO;FILE;SIZE;LIBID;DEL;EXT;DATE;LANG;LIBRATE;KEYWORDS;";
+const SEPARATOR: char = '\x04';
+
+#[derive(Debug, Default)]
+pub struct Record {
+    pub id: u32,
+    pub authors: Vec<Person>,
+    pub genres: Vec<String>,
+    pub title: String,
+    pub serie: String,
+    pub serie_num: u32,
+    pub size: u64,
+    pub deleted: bool,
+    pub lang: String,
+    pub added: String,
+}
+
+/// The reason why the record was not imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Skip {
+    Malformed,
+    NotFb2,
+    NoTitle,
+    NoArchive,
+}
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skip::Malformed => write!(f, "malformed record"),
+            Skip::NotFb2 => write!(f, "not FB2 book"),
+            Skip::NoTitle => write!(f, "empty title"),
+            Skip::NoArchive => write!(f, "archive not found"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Structure {
+    fields: Vec<String>,
+}
+impl Default for Structure {
+    fn default() -> Self {
+        Structure::from(DEFAULT_STRUCTURE)
+    }
+}
+impl From<&str> for Structure {
+    fn from(value: &str) -> Self {
+        let fields = value
+            .trim()
+            .split(';')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.trim().to_uppercase())
+            .collect();
+        Structure { fields }
+    }
+}
+impl Structure {
+    /// Parse the `.inp` line into the record
+    pub fn parse(&self, line: &str) -> Result<Record, Skip> {
+        let values: Vec<&str> = line
+            .trim_end_matches(['\r', '\n'])
+            .split(SEPARATOR)
+            .collect();
+        let get = |name: &str| {
+            self.fields
+                .iter()
+                .position(|f| f == name)
+                .and_then(|idx| values.get(idx))
+                .map_or("", |v| v.trim())
+        };
+
+        let ext = get("EXT");
+        if !ext.is_empty() && !ext.eq_ignore_ascii_case("fb2") {
+            return Err(Skip::NotFb2);
+        }
+        let id = get("FILE").parse::<u32>().map_err(|_| Skip::Malformed)?;
+        let title = get("TITLE");
+        if title.is_empty() {
+            return Err(Skip::NoTitle);
+        }
+
+        Ok(Record {
+            id,
+            authors: parse_authors(get("AUTHOR")),
+            genres: split(get("GENRE"), ':'),
+            title: title.to_string(),
+            serie: get("SERIES").to_string(),
+            serie_num: get("SERNO").parse().unwrap_or(0),
+            size: get("SIZE").parse().unwrap_or(0),
+            deleted: get("DEL") == "1",
+            lang: get("LANG").to_lowercase(),
+            added: get("DATE").to_string(),
+        })
+    }
+}
+
+fn split(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Authors are stored as `Last,First,Middle:Last,First,Middle:`
+fn parse_authors(value: &str) -> Vec<Person> {
+    split(value, ':')
+        .into_iter()
+        .map(|author| {
+            let mut names = author.split(',').map(str::trim);
+            Person {
+                last_name: names.next().unwrap_or_default().to_string(),
+                first_name: names.next().unwrap_or_default().to_string(),
+                middle_name: names.next().unwrap_or_default().to_string(),
+                nickname: String::new(),
+            }
+        })
+        .filter(|p| !p.last_name.is_empty() || !p.first_name.is_empty())
+        .collect()
+}
+
+/// The content of one `.inp` file
+#[derive(Debug)]
+pub struct Inp {
+    /// The name of the archive with books without extension, e.g. `fb2-000024-030559`
+    pub archive: String,
+    pub lines: Vec<String>,
+}
+
+/// Read the `.inpx` index, returns the records structure and the `.inp` files sorted by name
+pub fn read(path: &Path) -> anyhow::Result<(Structure, Vec<Inp>)> {
+    info!("Reading index {}", path.display());
+    let file = fs::File::open(path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    let mut structure = Structure::default();
+    let mut inps = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().to_string();
+        let mut content = String::new();
+        if name.eq_ignore_ascii_case("structure.info") {
+            file.read_to_string(&mut content)?;
+            structure = Structure::from(content.as_str());
+            debug!("structure: {structure:?}");
+        } else if let Some(archive) = name.strip_suffix(".inp") {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            content = String::from_utf8_lossy(&bytes).into_owned();
+            inps.push(Inp {
+                archive: archive.to_string(),
+                lines: content.lines().map(String::from).collect(),
+            });
+        }
+    }
+    inps.sort_by(|a, b| a.archive.cmp(&b.archive));
+    Ok((structure, inps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let line = "Стругацкий,Аркадий,Натанович:Стругацкий,Борис,Натанович:\x04sf_social:sf:\x04Пикник на обочине\x04Миры братьев Стругацких\x0412\x04123456\x04345678\x04123456\x040\x04fb2\x042009-02-28\x04ru\x04\x04\x04";
+        let record = Structure::default().parse(line).unwrap();
+        assert_eq!(123456, record.id);
+        assert_eq!(2, record.authors.len());
+        assert_eq!("Борис", record.authors[1].first_name);
+        assert_eq!(vec!["sf_social", "sf"], record.genres);
+        assert_eq!("Миры братьев Стругацких", record.serie);
+        assert_eq!(12, record.serie_num);
+        assert_eq!(345678, record.size);
+        assert!(!record.deleted);
+        assert_eq!("2009-02-28", record.added);
+    }
+
+    #[test]
+    fn test_skip() {
+        let structure = Structure::from("AUTHOR;TITLE;FILE;EXT;");
+        assert_eq!(
+            Skip::NotFb2,
+            structure.parse("A,B,\x04T\x041\x04pdf").unwrap_err()
+        );
+        assert_eq!(
+            Skip::Malformed,
+            structure.parse("A,B,\x04T\x04x\x04fb2").unwrap_err()
+        );
+        assert_eq!(
+            Skip::NoTitle,
+            structure.parse("A,B,\x04\x041\x04fb2").unwrap_err()
+        );
+    }
+}
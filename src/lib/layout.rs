@@ -0,0 +1,102 @@
+//! The correction of the text typed in the wrong keyboard layout.
+//!
+//! The keys of QWERTY and ЙЦУКЕН are matched by their places, so `cnheufwrbq` typed
+//! in the Latin layout is `стругацкий` and `лштп` typed in the Cyrillic one is `king`.
+
+/// The Cyrillic keyboard layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Russian,
+    Ukrainian,
+}
+impl Layout {
+    pub const ALL: [Layout; 2] = [Layout::Russian, Layout::Ukrainian];
+
+    /// Returns the Latin keys and the letters of the layout in the same places,
+    /// the shifted punctuation keys are paired with the capital letters
+    fn keys(&self) -> (&'static str, &'static str) {
+        match self {
+            Layout::Russian => (
+                "`qwertyuiop[]asdfghjkl;'zxcvbnm,.~{}:\"<>",
+                "ёйцукенгшщзхъфывапролджэячсмитьбюЁХЪЖЭБЮ",
+            ),
+            Layout::Ukrainian => (
+                "qwertyuiop[]asdfghjkl;'zxcvbnm,.\\{}:\"<>|",
+                "йцукенгшщзхїфівапролджєячсмитьбюґХЇЖЄБЮҐ",
+            ),
+        }
+    }
+}
+
+/// Returns the text typed in the other layout: the Latin keys as the letters of
+/// the layout and the letters of the layout as the Latin keys. The case is kept.
+pub fn swap(text: &str, layout: Layout) -> String {
+    let (latin, cyrillic) = layout.keys();
+    let find = |from: &str, to: &str, c: char| {
+        let idx = from.chars().position(|curr| curr == c)?;
+        to.chars().nth(idx)
+    };
+    let key = |c: char| find(latin, cyrillic, c).or_else(|| find(cyrillic, latin, c));
+    text.chars()
+        .map(|c| {
+            // the shifted keys first, `Ё` is typed as `~` and not as the capital backtick
+            if let Some(swapped) = key(c) {
+                return swapped;
+            }
+            let lower = c.to_lowercase().next().unwrap_or(c);
+            match key(lower) {
+                Some(swapped) if lower != c => swapped.to_uppercase().next().unwrap_or(swapped),
+                Some(swapped) => swapped,
+                None => c,
+            }
+        })
+        .collect()
+}
+
+/// Returns the distinct variants of the text typed in the other layouts,
+/// the lowercase variants are also capitalized like the names are written
+pub fn variants(text: &str) -> Vec<String> {
+    let mut variants = Vec::new();
+    for layout in Layout::ALL {
+        let swapped = swap(text, layout);
+        let mut chars = swapped.chars();
+        let capitalized = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+        for variant in [swapped, capitalized] {
+            if variant != text && !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap() {
+        assert_eq!("стругацкий", swap("cnheufwrbq", Layout::Russian));
+        assert_eq!("Стругацкий", swap("Cnheufwrbq", Layout::Russian));
+        assert_eq!("Ёжиков", swap("~;brjd", Layout::Russian));
+        assert_eq!("king", swap("лштп", Layout::Russian));
+        assert_eq!("Шевченко", swap("Itdxtyrj", Layout::Ukrainian));
+        assert_eq!("Ґонта", swap("|jynf", Layout::Ukrainian));
+        assert_eq!("sdfy", swap("іван", Layout::Ukrainian));
+
+        // the capital letters on the punctuation keys are typed with the shift
+        assert_eq!("~;brjd", swap("Ёжиков", Layout::Russian));
+        assert_eq!("{j,,bn", swap("Хоббит", Layout::Russian));
+        assert_eq!(":\"<>}", swap("ЖЭБЮЪ", Layout::Russian));
+        assert_eq!("`[]", swap("ёхъ", Layout::Russian));
+        assert_eq!("|jynf", swap("Ґонта", Layout::Ukrainian));
+        assert_eq!("{}:\"<>", swap("ХЇЖЄБЮ", Layout::Ukrainian));
+        assert_eq!("Itdxtyrj", swap("Шевченко", Layout::Ukrainian));
+
+        let variants = variants("cnheufwrbq");
+        assert_eq!(vec!["стругацкий", "Стругацкий"], variants);
+    }
+}
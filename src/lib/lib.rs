@@ -1,6 +1,27 @@
 extern crate opds_api;
 
+pub mod access;
+pub mod aliases;
 pub mod books;
+pub mod catalog;
+pub mod compress;
+pub mod editions;
+pub mod facets;
+pub mod fb2;
+pub mod genres;
+pub mod i18n;
+pub mod import;
+pub mod inpx;
+pub mod layout;
+pub mod mail;
+pub mod metrics;
 pub mod opds;
+pub mod pages;
+pub mod reader;
 pub mod search;
 pub mod statistic;
+pub mod tls;
+pub mod translit;
+pub mod urls;
+pub mod user;
+pub mod watcher;
@@ -0,0 +1,293 @@
+//! Sending books by e-mail (Send-to-Kindle, PocketBook cloud and so on).
+//!
+//! The books are queued in the statistic database and sent by the background worker,
+//! the failed attempts are retried with the growing delay.
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Address, Message, SmtpTransport, Transport};
+use log::{error, info, warn};
+use zip::write::SimpleFileOptions;
+
+use crate::fb2;
+use crate::statistic::{Mail, StatisticApi};
+
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// The number of attempts to send the mail before it is marked as failed
+pub const MAX_ATTEMPTS: u32 = 5;
+/// The delay before the first retry in seconds, it is doubled for every next one
+const RETRY_DELAY: u64 = 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+const FB2_TYPE: &str = "application/x-fictionbook+xml";
+const ZIP_TYPE: &str = "application/zip";
+
+/// The connection security of the SMTP server
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// Plain connection, e.g. to the local relay or the test sink
+    None,
+    #[default]
+    StartTls,
+    /// Implicit TLS, usually the port 465
+    Tls,
+}
+impl From<&str> for Security {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "none" | "plain" => Security::None,
+            "tls" | "ssl" => Security::Tls,
+            _ => Security::StartTls,
+        }
+    }
+}
+
+/// The format of the attached book
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Fb2,
+    /// The book is compressed into `<id>.fb2.zip`
+    Zip,
+}
+impl From<&str> for Format {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "zip" | "fb2.zip" => Format::Zip,
+            _ => Format::Fb2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// The default port of the security mode is used if None
+    pub port: Option<u16>,
+    pub security: Security,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub format: Format,
+}
+
+/// Returns true if the address may be used as the recipient
+pub fn is_valid_address(address: &str) -> bool {
+    address.parse::<Address>().is_ok()
+}
+
+pub struct Mailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+    format: Format,
+}
+impl Mailer {
+    pub fn new(config: &SmtpConfig) -> anyhow::Result<Self> {
+        let mut builder = match config.security {
+            Security::None => SmtpTransport::builder_dangerous(&config.host),
+            Security::StartTls => SmtpTransport::starttls_relay(&config.host)?,
+            Security::Tls => SmtpTransport::relay(&config.host)?,
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if !config.username.is_empty() {
+            let credentials = Credentials::new(config.username.clone(), config.password.clone());
+            builder = builder.credentials(credentials);
+        }
+        Ok(Mailer {
+            transport: builder.build(),
+            from: config.from.parse()?,
+            format: config.format,
+        })
+    }
+
+    /// Send the book file to the address as the attachment
+    pub fn send(&self, to: &str, path: &Path) -> anyhow::Result<()> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("book.fb2"));
+        let title = fb2::read_description(path)
+            .map(|desc| desc.title)
+            .unwrap_or_else(|_| name.clone());
+
+        let (name, body, content_type) = match self.format {
+            Format::Fb2 => (name, fs::read(path)?, FB2_TYPE),
+            Format::Zip => (format!("{name}.zip"), compress(path, &name)?, ZIP_TYPE),
+        };
+        let attachment = Attachment::new(name).body(body, ContentType::parse(content_type)?);
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(title.clone())
+            .multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(title))
+                    .singlepart(attachment),
+            )?;
+        self.transport.send(&message)?;
+        Ok(())
+    }
+}
+
+/// Returns the content of the zip archive with the single file
+fn compress(path: &Path, name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(name, SimpleFileOptions::default())?;
+    zip.write_all(&fs::read(path)?)?;
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Returns the delay in seconds before the next attempt: 1, 2, 4, 8 minutes
+fn retry_delay(attempts: u32) -> u64 {
+    RETRY_DELAY << attempts.saturating_sub(1).min(10)
+}
+
+/// Wakes up the worker when the new mail is queued
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    wake: Sender<()>,
+}
+impl Outbox {
+    pub fn notify(&self) {
+        let _ = self.wake.send(());
+    }
+}
+
+/// Start the worker sending the queued mails, `extract` returns the path of the book file.
+/// The worker also wakes up periodically to retry the failed attempts.
+pub fn spawn<F>(mailer: Mailer, stat: StatisticApi, extract: F) -> Outbox
+where
+    F: Fn(u32) -> io::Result<PathBuf> + Send + 'static,
+{
+    let (wake, rx) = mpsc::channel();
+    thread::spawn(move || loop {
+        if let Err(err) = process(&mailer, &stat, &extract) {
+            error!("Mail queue: {err}");
+        }
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(()) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    });
+    Outbox { wake }
+}
+
+fn process<F>(mailer: &Mailer, stat: &StatisticApi, extract: &F) -> anyhow::Result<()>
+where
+    F: Fn(u32) -> io::Result<PathBuf>,
+{
+    for Mail {
+        id,
+        book_id,
+        address,
+        attempts,
+        ..
+    } in stat.pending_mails()?
+    {
+        let result = extract(book_id)
+            .map_err(anyhow::Error::from)
+            .and_then(|path| mailer.send(&address, &path));
+        match result {
+            Ok(()) => {
+                info!("The book {book_id} has been sent to {address}");
+                stat.mail_sent(id)?;
+            }
+            Err(err) => {
+                let attempts = attempts + 1;
+                let retry = (attempts < MAX_ATTEMPTS).then(|| retry_delay(attempts));
+                warn!("The book {book_id} to {address}, attempt {attempts}: {err}");
+                stat.mail_failed(id, &err.to_string(), retry)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    /// Accepts the single SMTP session and returns the received message
+    fn sink(listener: TcpListener) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut data = String::new();
+            let mut in_data = false;
+            writer.write_all(b"220 sink ESMTP\r\n").unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        writer.write_all(b"250 OK\r\n").unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                } else {
+                    let command = line.to_uppercase();
+                    let reply: &[u8] = match command.get(..4).unwrap_or_default() {
+                        "DATA" => {
+                            in_data = true;
+                            b"354 Go ahead\r\n"
+                        }
+                        "QUIT" => {
+                            writer.write_all(b"221 Bye\r\n").unwrap();
+                            break;
+                        }
+                        _ => b"250 OK\r\n",
+                    };
+                    writer.write_all(reply).unwrap();
+                }
+                line.clear();
+            }
+            data
+        })
+    }
+
+    #[test]
+    fn test_send_to_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = sink(listener);
+
+        let path = std::env::temp_dir().join(format!("mail-test-{port}.fb2"));
+        fs::write(&path, "<FictionBook><body>text</body></FictionBook>").unwrap();
+
+        let config = SmtpConfig {
+            host: String::from("127.0.0.1"),
+            port: Some(port),
+            security: Security::None,
+            from: String::from("library@localhost"),
+            format: Format::Zip,
+            ..SmtpConfig::default()
+        };
+        let mailer = Mailer::new(&config).unwrap();
+        mailer.send("reader@kindle.com", &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let data = received.join().unwrap();
+        assert!(data.contains("To: reader@kindle.com"));
+        assert!(data.contains(&format!("mail-test-{port}.fb2.zip")));
+        assert!(data.contains(ZIP_TYPE));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(60, retry_delay(1));
+        assert_eq!(480, retry_delay(4));
+        assert!(is_valid_address("reader@kindle.com"));
+        assert!(!is_valid_address("reader"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Server metrics exposed in the Prometheus text format.
+//!
+//! The databases are not timed per query, `opds_db_lock_held_seconds` is the time a request
+//! holds the database lock: the queries and the work done with their results under the lock.
+//! The time spent waiting for the lock is not included, it is a part of the request time.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+#[derive(Debug, Default, Clone)]
+pub struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+impl Histogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (count, bound) in self.counts.iter_mut().zip(BUCKETS) {
+            if secs <= bound {
+                *count += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (count, bound) in self.counts.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{labels} {}", self.count);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Requests count by (route, method, status)
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Requests latency by route
+    latencies: BTreeMap<String, Histogram>,
+    downloads: u64,
+    download_bytes: u64,
+    extraction: Histogram,
+    /// Time of holding the database connection by database name
+    locks: BTreeMap<&'static str, Histogram>,
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+    started: Instant,
+}
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            registry: Mutex::new(Registry::default()),
+            started: Instant::now(),
+        }
+    }
+
+    fn update<F: FnOnce(&mut Registry)>(&self, f: F) {
+        if let Ok(mut registry) = self.registry.lock() {
+            f(&mut registry);
+        }
+    }
+
+    /// Count the handled request, `route` is the matched pattern like `/opds/book/id/{id}`
+    pub fn request(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        self.update(|r| {
+            let key = (String::from(route), String::from(method), status);
+            *r.requests.entry(key).or_default() += 1;
+            r.latencies
+                .entry(String::from(route))
+                .or_default()
+                .observe(elapsed);
+        });
+    }
+
+    pub fn download(&self, bytes: u64) {
+        self.update(|r| {
+            r.downloads += 1;
+            r.download_bytes += bytes;
+        });
+    }
+
+    pub fn extraction(&self, elapsed: Duration) {
+        self.update(|r| r.extraction.observe(elapsed));
+    }
+
+    pub fn lock_held(&self, database: &'static str, elapsed: Duration) {
+        self.update(|r| r.locks.entry(database).or_default().observe(elapsed));
+    }
+
+    /// Wrap the database guard to measure the time it is held, from the lock acquired
+    /// to the guard dropped
+    pub fn timed<G>(&self, database: &'static str, guard: G) -> Timed<'_, G> {
+        Timed {
+            guard,
+            database,
+            metrics: self,
+            started: Instant::now(),
+        }
+    }
+
+    /// Returns the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let Ok(r) = self.registry.lock() else {
+            return out;
+        };
+
+        out.push_str("# HELP opds_uptime_seconds Time since the server start.\n");
+        out.push_str("# TYPE opds_uptime_seconds gauge\n");
+        let uptime = self.started.elapsed().as_secs_f64();
+        let _ = writeln!(out, "opds_uptime_seconds {uptime}");
+
+        out.push_str("# HELP opds_http_requests_total Handled HTTP requests.\n");
+        out.push_str("# TYPE opds_http_requests_total counter\n");
+        for ((route, method, status), count) in r.requests.iter() {
+            let labels = format!("route=\"{route}\",method=\"{method}\",status=\"{status}\"");
+            let _ = writeln!(out, "opds_http_requests_total{{{labels}}} {count}");
+        }
+
+        out.push_str("# HELP opds_http_request_duration_seconds HTTP requests latency.\n");
+        out.push_str("# TYPE opds_http_request_duration_seconds histogram\n");
+        for (route, histogram) in r.latencies.iter() {
+            let labels = format!("route=\"{route}\"");
+            histogram.write(&mut out, "opds_http_request_duration_seconds", &labels);
+        }
+
+        out.push_str("# HELP opds_downloads_total Downloaded books.\n");
+        out.push_str("# TYPE opds_downloads_total counter\n");
+        let _ = writeln!(out, "opds_downloads_total {}", r.downloads);
+        out.push_str("# HELP opds_download_bytes_total Size of downloaded books.\n");
+        out.push_str("# TYPE opds_download_bytes_total counter\n");
+        let _ = writeln!(out, "opds_download_bytes_total {}", r.download_bytes);
+
+        out.push_str("# HELP opds_extraction_seconds Time of extracting books from archives.\n");
+        out.push_str("# TYPE opds_extraction_seconds histogram\n");
+        r.extraction.write(&mut out, "opds_extraction_seconds", "");
+
+        out.push_str("# HELP opds_db_lock_held_seconds Time the database locks are held, the waits excluded.\n");
+        out.push_str("# TYPE opds_db_lock_held_seconds histogram\n");
+        for (database, histogram) in r.locks.iter() {
+            let labels = format!("db=\"{database}\"");
+            histogram.write(&mut out, "opds_db_lock_held_seconds", &labels);
+        }
+        out
+    }
+}
+
+/// The guard which reports the time it was held to the metrics when dropped
+pub struct Timed<'a, G> {
+    guard: G,
+    database: &'static str,
+    metrics: &'a Metrics,
+    started: Instant,
+}
+impl<G> Deref for Timed<'_, G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.guard
+    }
+}
+impl<G> DerefMut for Timed<'_, G> {
+    fn deref_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+impl<G> Drop for Timed<'_, G> {
+    fn drop(&mut self) {
+        self.metrics
+            .lock_held(self.database, self.started.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        let route = "/opds/book/id/{id}";
+        metrics.request(route, "GET", 200, Duration::from_millis(20));
+        metrics.request(route, "GET", 200, Duration::from_millis(200));
+        metrics.download(1024);
+        drop(metrics.timed("catalog", ()));
+
+        let text = metrics.render();
+        let requests =
+            r#"opds_http_requests_total{route="/opds/book/id/{id}",method="GET",status="200"} 2"#;
+        assert!(text.contains(requests));
+        let bucket =
+            r#"opds_http_request_duration_seconds_bucket{route="/opds/book/id/{id}",le="0.025"} 1"#;
+        assert!(text.contains(bucket));
+        assert!(text.contains("opds_download_bytes_total 1024"));
+        assert!(text.contains("opds_db_lock_held_seconds_count{db=\"catalog\"} 1"));
+    }
+}
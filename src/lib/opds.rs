@@ -1,17 +1,55 @@
-use actix_web::{Responder, Result};
+use actix_web::body::BoxBody;
+use actix_web::{HttpRequest, HttpResponse, Responder, Result};
 use chrono;
 use log::error;
 use quick_xml::events::{BytesDecl, BytesText, Event};
 use quick_xml::writer::Writer;
 
+use crate::facets::FACET_REL;
+use crate::urls::Urls;
+
 use std::io::Cursor;
 
+pub const CATALOG_TYPE: &str = "application/atom+xml;profile=opds-catalog";
+pub const ENTRY_TYPE: &str = "application/atom+xml;type=entry;profile=opds-catalog";
+pub const ATOM_TYPE: &str = "application/atom+xml";
+
+#[derive(Debug)]
+pub struct Link {
+    pub rel: String,
+    pub title: String,
+    pub href: String,
+    pub htype: String,
+}
+
+/// The link to the same list narrowed by the facet
+#[derive(Debug)]
+pub struct Facet {
+    pub group: String,
+    pub title: String,
+    pub href: String,
+    /// The number of items in the narrowed list if it is known
+    pub count: Option<u32>,
+    /// The facet is selected for the current list
+    pub active: bool,
+}
+
 #[derive(Debug)]
 pub struct Entry {
     pub id: String,
     pub title: String,
     pub href: String,
     pub htype: String,
+    pub authors: Vec<String>,
+    pub contributors: Vec<String>,
+    pub categories: Vec<String>,
+    pub dc: Vec<(&'static str, String)>,
+    /// The name of the series and the number of the book in it
+    pub serie: Option<(String, u32)>,
+    pub content: Option<String>,
+    pub links: Vec<Link>,
+    /// The time of the last change in RFC 3339
+    pub updated: Option<String>,
 }
 impl Entry {
     pub fn catalog<T: Into<String>>(title: T, link: T) -> Self {
@@ -21,7 +59,8 @@ impl Entry {
             id: id.into(),
             title: title.into(),
             href: href.into(),
-            htype: String::from("application/atom+xml;profile=opds-catalog"),
+            htype: String::from(CATALOG_TYPE),
+            ..Self::empty()
         }
     }
 
@@ -33,41 +72,153 @@ impl Entry {
             title: title.into(),
             href: href.into(),
             htype: String::from("application/fb2+zip"),
+            ..Self::empty()
+        }
+    }
+
+    /// The entry without links like the notes in the list, the id must be unique in the feed
+    pub fn text<T: Into<String>>(id: T, title: T) -> Self {
+        Self {
+            id: id.into(),
+            title: title.into(),
+            ..Self::empty()
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            id: String::new(),
+            title: String::new(),
+            href: String::new(),
+            htype: String::new(),
+            authors: Vec::new(),
+            contributors: Vec::new(),
+            categories: Vec::new(),
+            dc: Vec::new(),
+            serie: None,
+            content: None,
+            links: Vec::new(),
+            updated: None,
         }
     }
+
+    /// Add the additional link to the entry
+    pub fn link<T: Into<String>>(&mut self, rel: T, title: T, href: T, htype: T) -> &mut Self {
+        self.links.push(Link {
+            rel: rel.into(),
+            title: title.into(),
+            href: href.into(),
+            htype: htype.into(),
+        });
+        self
+    }
 }
 
 #[derive(Debug)]
 pub struct Feed {
     pub title: String,
     pub entries: Vec<Entry>,
+    /// The permanent id of the feed, required by the feed readers
+    pub id: Option<String>,
+    /// The time of the last change in RFC 3339, the current time if None
+    pub updated: Option<String>,
+    /// The links of the feed itself like `self` or `next`
+    pub links: Vec<Link>,
+    pub facets: Vec<Facet>,
 }
 impl Feed {
     pub fn new<T: Into<String>>(title: T) -> Self {
         Self {
             title: title.into(),
             entries: Vec::new(),
+            id: None,
+            updated: None,
+            links: Vec::new(),
+            facets: Vec::new(),
         }
     }
 
+    /// Add the facet of the group to the feed
+    pub fn facet<T: Into<String>>(
+        &mut self,
+        group: T,
+        title: T,
+        href: T,
+        count: Option<u32>,
+        active: bool,
+    ) -> &mut Self {
+        self.facets.push(Facet {
+            group: group.into(),
+            title: title.into(),
+            href: href.into(),
+            count,
+            active,
+        });
+        self
+    }
+
+    /// Add the link to the feed itself
+    pub fn link<T: Into<String>>(&mut self, rel: T, title: T, href: T, htype: T) -> &mut Self {
+        self.links.push(Link {
+            rel: rel.into(),
+            title: title.into(),
+            href: href.into(),
+            htype: htype.into(),
+        });
+        self
+    }
+
     pub fn catalog<T: Into<String>>(&mut self, title: T, link: T) {
         let entry = Entry::catalog(title, link);
         self.entries.push(entry);
     }
 
-    pub fn book<T: Into<String>>(&mut self, title: T, link: T) {
+    pub fn book<T: Into<String>>(&mut self, title: T, link: T) -> &mut Entry {
         let entry = Entry::book(title, link);
+        self.push(entry)
+    }
+
+    pub fn push(&mut self, entry: Entry) -> &mut Entry {
+        let idx = self.entries.len();
         self.entries.push(entry);
+        &mut self.entries[idx]
     }
 
     pub fn format(self) -> Result<impl Responder> {
-        let content = format_feed(self);
-        Ok(content)
+        Ok(FeedResponse {
+            feed: self,
+            content_type: None,
+        })
+    }
+
+    /// Respond with the plain Atom feed for the feed readers
+    pub fn atom(self) -> Result<impl Responder> {
+        Ok(FeedResponse {
+            feed: self,
+            content_type: Some("application/atom+xml; charset=utf-8"),
+        })
+    }
+}
+
+/// The feed which links are resolved to the public URLs of the request when responding
+pub struct FeedResponse {
+    feed: Feed,
+    content_type: Option<&'static str>,
+}
+impl Responder for FeedResponse {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let content = format_feed(self.feed, &Urls::from(req));
+        match self.content_type {
+            Some(content_type) => HttpResponse::Ok().content_type(content_type).body(content),
+            None => content.respond_to(req).map_into_boxed_body(),
+        }
     }
 }
 
-fn format_feed(feed: Feed) -> String {
-    match make_feed(feed) {
+fn format_feed(feed: Feed, urls: &Urls) -> String {
+    match make_feed(feed, urls) {
         Ok(xml) => xml,
         Err(err) => format!("{err}"),
     }
@@ -75,7 +226,7 @@ fn format_feed(feed: Feed) -> String {
 
 pub fn handle_feed(feed_result: anyhow::Result<Feed>) -> impl Responder {
     match feed_result {
-        Ok(feed) => format_feed(feed),
+        Ok(feed) => format_feed(feed, &Urls::default()),
         Err(err) => {
             let msg = format!("{}", err);
             error!("failure: {}", msg);
@@ -84,7 +235,7 @@ pub fn handle_feed(feed_result: anyhow::Result<Feed>) -> impl Responder {
     }
 }
 
-fn make_feed(feed: Feed) -> anyhow::Result<String> {
+fn make_feed(feed: Feed, urls: &Urls) -> anyhow::Result<String> {
     let mut w = Writer::new(Cursor::new(Vec::new()));
 
     const XML_VERSION: &'static str = "1.0";
@@ -101,20 +252,57 @@ fn make_feed(feed: Feed) -> anyhow::Result<String> {
         .with_attribute(("xmlns:dc", "http://purl.org/dc/terms/"))
         .with_attribute(("xmlns:os", "http://a9.com/-/spec/opensearch/1.1/"))
         .with_attribute(("xmlns:opds", "http://opds-spec.org/2010/catalog"))
+        .with_attribute(("xmlns:thr", "http://purl.org/syndication/thread/1.0"))
+        .with_attribute(("xmlns:schema", "http://schema.org/"))
         .write_inner_content(|w| {
+            if let Some(id) = &feed.id {
+                w.create_element("id")
+                    .write_text_content(BytesText::new(id))?;
+            }
+
             w.create_element("title")
                 .write_text_content(BytesText::new(&feed.title))?;
 
-            let updated = format!("{:?}", chrono::Utc::now());
+            let updated = match &feed.updated {
+                Some(updated) => updated.clone(),
+                None => format!("{:?}", chrono::Utc::now()),
+            };
             w.create_element("updated")
                 .write_text_content(BytesText::new(&updated))?;
 
             w.create_element("link")
-                .with_attribute(("href", "/opds"))
+                .with_attribute(("href", urls.url("/opds").as_str()))
                 .with_attribute(("rel", "/start"))
                 .with_attribute(("type", "application/atom+xml;profile=opds-catalog"))
                 .write_empty()?;
 
+            for link in &feed.links {
+                w.create_element("link")
+                    .with_attribute(("rel", link.rel.as_str()))
+                    .with_attribute(("title", link.title.as_str()))
+                    .with_attribute(("href", urls.url(&link.href).as_str()))
+                    .with_attribute(("type", link.htype.as_str()))
+                    .write_empty()?;
+            }
+
+            for facet in &feed.facets {
+                let count = facet.count.map(|count| count.to_string());
+                let mut link = w
+                    .create_element("link")
+                    .with_attribute(("rel", FACET_REL))
+                    .with_attribute(("title", facet.title.as_str()))
+                    .with_attribute(("href", urls.url(&facet.href).as_str()))
+                    .with_attribute(("type", CATALOG_TYPE))
+                    .with_attribute(("opds:facetGroup", facet.group.as_str()));
+                if facet.active {
+                    link = link.with_attribute(("opds:activeFacet", "true"));
+                }
+                if let Some(count) = &count {
+                    link = link.with_attribute(("thr:count", count.as_str()));
+                }
+                link.write_empty()?;
+            }
+
             for entry in &feed.entries {
                 w.create_element("entry").write_inner_content(|w| {
                     w.create_element("id")
@@ -123,10 +311,71 @@ fn make_feed(feed: Feed) -> anyhow::Result<String> {
                     w.create_element("title")
                         .write_text_content(BytesText::new(&entry.title))?;
 
-                    w.create_element("link")
-                        .with_attribute(("href", entry.href.as_str()))
-                        .with_attribute(("type", entry.htype.as_str()))
-                        .write_empty()?;
+                    if let Some(updated) = &entry.updated {
+                        w.create_element("updated")
+                            .write_text_content(BytesText::new(updated))?;
+                    }
+
+                    for author in &entry.authors {
+                        w.create_element("author").write_inner_content(|w| {
+                            w.create_element("name")
+                                .write_text_content(BytesText::new(author))?;
+                            Ok::<(), quick_xml::Error>(())
+                        })?;
+                    }
+
+                    for contributor in &entry.contributors {
+                        w.create_element("contributor").write_inner_content(|w| {
+                            w.create_element("name")
+                                .write_text_content(BytesText::new(contributor))?;
+                            Ok::<(), quick_xml::Error>(())
+                        })?;
+                    }
+
+                    for category in &entry.categories {
+                        w.create_element("category")
+                            .with_attribute(("term", category.as_str()))
+                            .with_attribute(("label", category.as_str()))
+                            .write_empty()?;
+                    }
+
+                    for (name, value) in &entry.dc {
+                        w.create_element(format!("dc:{name}"))
+                            .write_text_content(BytesText::new(value))?;
+                    }
+
+                    if let Some((name, num)) = &entry.serie {
+                        let mut serie = w
+                            .create_element("schema:Series")
+                            .with_attribute(("schema:name", name.as_str()));
+                        let position = num.to_string();
+                        if *num > 0 {
+                            serie = serie.with_attribute(("schema:position", position.as_str()));
+                        }
+                        serie.write_empty()?;
+                    }
+
+                    if let Some(content) = &entry.content {
+                        w.create_element("content")
+                            .with_attribute(("type", "text"))
+                            .write_text_content(BytesText::new(content))?;
+                    }
+
+                    if !entry.href.is_empty() {
+                        w.create_element("link")
+                            .with_attribute(("href", urls.url(&entry.href).as_str()))
+                            .with_attribute(("type", entry.htype.as_str()))
+                            .write_empty()?;
+                    }
+
+                    for link in &entry.links {
+                        w.create_element("link")
+                            .with_attribute(("rel", link.rel.as_str()))
+                            .with_attribute(("title", link.title.as_str()))
+                            .with_attribute(("href", urls.url(&link.href).as_str()))
+                            .with_attribute(("type", link.htype.as_str()))
+                            .write_empty()?;
+                    }
 
                     Ok::<(), quick_xml::Error>(())
                 })?;
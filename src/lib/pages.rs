@@ -0,0 +1,190 @@
+//! The HTML pages of the server which are not the books: the messages, the user settings
+//! and the administration of the authors. They share the document layout of the reader.
+use quick_xml::escape::escape;
+
+use crate::catalog::Author;
+use crate::i18n::Locale;
+use crate::reader::document;
+use crate::statistic::{Mail, MailStatus};
+use crate::urls::Urls;
+
+/// Render the short message with the link back
+pub fn format_message(title: &str, message: &str, back: &str) -> String {
+    let nav = format!(r#"<nav><a href="{}">&larr;</a></nav>"#, escape(back));
+    let content = format!("<p>{}</p>", escape(message));
+    document(title, &nav, &content)
+}
+
+/// Render the question with the button which posts the form with the `token` to `action`
+pub fn format_confirm(
+    title: &str,
+    message: &str,
+    action: &str,
+    button: &str,
+    token: &str,
+    back: &str,
+) -> String {
+    let nav = format!(r#"<nav><a href="{}">&larr;</a></nav>"#, escape(back));
+    let content = format!(
+        r#"<p>{}</p>
+<form method="post" action="{}"><input type="hidden" name="token" value="{}"/><button>{}</button></form>"#,
+        escape(message),
+        escape(action),
+        escape(token),
+        escape(button)
+    );
+    document(title, &nav, &content)
+}
+
+/// The settings of the user shown on the settings page
+#[derive(Debug)]
+pub struct Settings<'a> {
+    /// The address the books are sent to, None for the anonymous user who may not send them
+    pub email: Option<&'a str>,
+    /// The default language of the books, empty for all languages
+    pub lang: &'a str,
+    /// The languages of the catalog with the number of books
+    pub langs: &'a [(String, u32)],
+    /// The recent mails to the address
+    pub mails: &'a [Mail],
+}
+
+/// Render the settings of the user with the recent mails, the form is posted with the `token`
+pub fn format_settings(
+    locale: Locale,
+    settings: &Settings,
+    error: Option<&str>,
+    token: &str,
+    urls: &Urls,
+) -> String {
+    let title = locale.tr("settings.title");
+    let mut content = format!("<h1>{}</h1>\n", escape(title));
+    if let Some(error) = error {
+        content.push_str(&format!("<p><b>{}</b></p>\n", escape(error)));
+    }
+    let mut options = format!(
+        r#"<option value="">{}</option>"#,
+        escape(locale.tr("facet.lang.all"))
+    );
+    for (code, count) in settings.langs.iter().filter(|(code, _)| !code.is_empty()) {
+        let selected = match code == settings.lang {
+            true => " selected",
+            false => "",
+        };
+        options.push_str(&format!(
+            r#"<option value="{}"{selected}>{} ({count})</option>"#,
+            escape(code),
+            escape(locale.language(code))
+        ));
+    }
+    let email = match settings.email {
+        Some(email) => format!(
+            r#"<label>{}<br/><input type="email" name="email" value="{}"/></label>"#,
+            escape(locale.tr("settings.email")),
+            escape(email)
+        ),
+        None => escape(locale.tr("send.anonymous")).into_owned(),
+    };
+    content.push_str(&format!(
+        r#"<form method="post" action="{}">
+<input type="hidden" name="token" value="{}"/>
+<p>{email}</p>
+<p><label>{}<br/><select name="lang">{options}</select></label></p>
+<p><button type="submit">{}</button></p>
+</form>
+"#,
+        urls.url("/settings"),
+        escape(token),
+        escape(locale.tr("settings.lang")),
+        escape(locale.tr("settings.save"))
+    ));
+    if !settings.mails.is_empty() {
+        let caption = escape(locale.tr("settings.mails"));
+        content.push_str(&format!("<h2>{caption}</h2>\n<ul>\n"));
+        for mail in settings.mails {
+            let status = match mail.status {
+                MailStatus::Queued => locale.tr("mail.queued"),
+                MailStatus::Sent => locale.tr("mail.sent"),
+                MailStatus::Failed => locale.tr("mail.failed"),
+            };
+            let error = mail.error.as_deref().unwrap_or_default();
+            content.push_str(&format!(
+                r#"<li>{} <a href="{}">#{}</a> {} {}</li>"#,
+                mail.updated,
+                urls.url(&format!("/read/{}", mail.book_id)),
+                mail.book_id,
+                status,
+                escape(error)
+            ));
+            content.push('\n');
+        }
+        content.push_str("</ul>");
+    }
+    let nav = format!(r#"<nav><a href="{}">OPDS</a></nav>"#, urls.url("/opds"));
+    document(title, &nav, &content)
+}
+
+/// Render the page of the merged authors as `(alias, canonical)` with the forms
+/// to split them and to merge the suggested ones, the forms are posted with the `token`
+pub fn format_aliases(
+    locale: Locale,
+    aliases: &[(Author, Author)],
+    suggestions: &[(Author, Author)],
+    error: Option<&str>,
+    token: &str,
+    urls: &Urls,
+) -> String {
+    let title = locale.tr("admin.authors");
+    let mut content = format!("<h1>{}</h1>\n", escape(title));
+    if let Some(error) = error {
+        content.push_str(&format!("<p><b>{}</b></p>\n", escape(error)));
+    }
+    let action = urls.url("/admin/authors");
+    let token = escape(token);
+    let ids = |author: &Author| {
+        let (fid, mid, lid) = author.ids;
+        format!("{fid}/{mid}/{lid}")
+    };
+    let form = |alias: &Author, canonical: &Author, name: &str, button: &str| {
+        format!(
+            r#"<form method="post" action="{action}"><input type="hidden" name="token" value="{token}"/><input type="hidden" name="action" value="{name}"/><input type="hidden" name="alias" value="{}"/><input type="hidden" name="canonical" value="{}"/>{} &rarr; {} <button type="submit">{}</button></form>"#,
+            ids(alias),
+            ids(canonical),
+            escape(&alias.to_string()),
+            escape(&canonical.to_string()),
+            escape(button)
+        )
+    };
+
+    content.push_str(&format!(
+        r#"<form method="post" action="{action}">
+<input type="hidden" name="token" value="{token}"/>
+<input type="hidden" name="action" value="merge"/>
+<p><label>{}<br/><input name="alias" placeholder="1/2/3"/></label></p>
+<p><label>{}<br/><input name="canonical" placeholder="1/2/3"/></label></p>
+<p><button type="submit">{}</button></p>
+</form>
+"#,
+        escape(locale.tr("admin.alias")),
+        escape(locale.tr("admin.canonical")),
+        escape(locale.tr("admin.merge"))
+    ));
+    let sections = [
+        ("admin.aliases", aliases, "split", "admin.split"),
+        ("admin.suggestions", suggestions, "merge", "admin.merge"),
+    ];
+    for (caption, pairs, name, button) in sections {
+        if pairs.is_empty() {
+            continue;
+        }
+        let caption = escape(locale.tr(caption));
+        content.push_str(&format!("<h2>{caption}</h2>\n<ul>\n"));
+        for (alias, canonical) in pairs.iter() {
+            let form = form(alias, canonical, name, locale.tr(button));
+            content.push_str(&format!("<li>{form}</li>\n"));
+        }
+        content.push_str("</ul>\n");
+    }
+    let nav = format!(r#"<nav><a href="{}">OPDS</a></nav>"#, urls.url("/opds"));
+    document(title, &nav, &content)
+}
@@ -0,0 +1,586 @@
+use log::debug;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+
+use crate::fb2::{attribute, decode};
+use crate::urls::Urls;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Approximate size of the rendered page in bytes
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Maps the FB2 element id to its content
+type Refs = HashMap<String, String>;
+
+#[derive(Debug)]
+pub struct TocEntry {
+    pub title: String,
+    pub level: usize,
+    pub page: usize,
+    pub anchor: String,
+}
+
+#[derive(Debug)]
+pub struct Book {
+    pub title: String,
+    pub pages: Vec<String>,
+    pub toc: Vec<TocEntry>,
+}
+
+/// The recently read books, the pages are turned without parsing the book again
+#[derive(Debug)]
+pub struct Cache {
+    capacity: usize,
+    /// The books by ids, the most recently read one is the last
+    books: Mutex<VecDeque<(u32, Arc<Book>)>>,
+}
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            capacity,
+            books: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Returns the cached book or the one returned by `load`, the least recently read
+    /// book is dropped when the cache is full. The cache is not locked while loading.
+    pub fn get_or_load<F>(&self, id: u32, load: F) -> anyhow::Result<Arc<Book>>
+    where
+        F: FnOnce() -> anyhow::Result<Book>,
+    {
+        if let Ok(mut books) = self.books.lock() {
+            let pos = books.iter().position(|(cached, _)| *cached == id);
+            if let Some((id, book)) = pos.and_then(|pos| books.remove(pos)) {
+                books.push_back((id, book.clone()));
+                return Ok(book);
+            }
+        }
+        let book = Arc::new(load()?);
+        if let Ok(mut books) = self.books.lock() {
+            books.retain(|(cached, _)| *cached != id);
+            if books.len() >= self.capacity {
+                books.pop_front();
+            }
+            if self.capacity > 0 {
+                books.push_back((id, book.clone()));
+            }
+        }
+        Ok(book)
+    }
+
+    /// Drop the cached books, e.g. when the library is reloaded
+    pub fn clear(&self) {
+        if let Ok(mut books) = self.books.lock() {
+            books.clear();
+        }
+    }
+}
+
+/// Load the FB2 file and render it into the paginated HTML
+pub fn load(path: &Path) -> anyhow::Result<Book> {
+    let bytes = fs::read(path)?;
+    parse(&decode(&bytes)?)
+}
+
+/// Render the decoded FB2 document into the paginated HTML
+fn parse(xml: &str) -> anyhow::Result<Book> {
+    let (title, notes, images) = collect(xml)?;
+    let (pages, toc) = render(xml, &notes, &images)?;
+    debug!("{title}: {} pages, {} toc entries", pages.len(), toc.len());
+    Ok(Book { title, pages, toc })
+}
+
+/// Collect the book title, the footnotes and the binary images
+fn collect(xml: &str) -> anyhow::Result<(String, Refs, Refs)> {
+    let mut reader = Reader::from_str(xml);
+    let mut title = String::new();
+    let mut notes = HashMap::new();
+    let mut images = HashMap::new();
+
+    let mut in_title = false;
+    let mut in_notes = false;
+    let mut in_note_title = false;
+    let mut opened: Vec<(String, String)> = Vec::new();
+    let mut binary: Option<(String, String, String)> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"book-title" => in_title = true,
+                b"body" => {
+                    let name = attribute(&e, b"name").unwrap_or_default();
+                    in_notes = name == "notes" || name == "comments";
+                }
+                b"title" if in_notes => in_note_title = true,
+                b"section" if in_notes => {
+                    let id = attribute(&e, b"id").unwrap_or_default();
+                    opened.push((id, String::new()));
+                }
+                b"binary" => {
+                    let id = attribute(&e, b"id").unwrap_or_default();
+                    let ctype = attribute(&e, b"content-type").unwrap_or_default();
+                    binary = Some((id, ctype, String::new()));
+                }
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"book-title" => in_title = false,
+                b"body" => in_notes = false,
+                b"title" if in_notes => in_note_title = false,
+                b"section" if in_notes => {
+                    if let Some((id, text)) = opened.pop() {
+                        if !id.is_empty() {
+                            notes.insert(id, text.trim().to_string());
+                        }
+                    }
+                }
+                b"binary" => {
+                    if let Some((id, ctype, data)) = binary.take() {
+                        match image_src(&ctype, &data) {
+                            Some(src) => images.insert(id, src),
+                            None => continue,
+                        };
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(e) => {
+                let text = e.unescape()?;
+                if in_title {
+                    title.push_str(&text);
+                } else if let Some((_, _, data)) = binary.as_mut() {
+                    data.extend(text.chars().filter(|c| !c.is_whitespace()));
+                } else if in_note_title {
+                    continue;
+                } else if let Some((_, note)) = opened.last_mut() {
+                    note.push_str(&text);
+                    note.push(' ');
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((title.trim().to_string(), notes, images))
+}
+
+fn html_tag(name: &[u8]) -> Option<&'static str> {
+    match name {
+        b"p" => Some("p"),
+        b"emphasis" => Some("em"),
+        b"strong" => Some("strong"),
+        b"strikethrough" => Some("s"),
+        b"sub" => Some("sub"),
+        b"sup" => Some("sup"),
+        b"code" => Some("code"),
+        b"table" => Some("table"),
+        b"tr" => Some("tr"),
+        b"td" => Some("td"),
+        b"th" => Some("th"),
+        _ => None,
+    }
+}
+
+fn html_class(name: &[u8]) -> Option<(&'static str, &'static str)> {
+    match name {
+        b"epigraph" => Some(("blockquote", "epigraph")),
+        b"cite" => Some(("blockquote", "cite")),
+        b"poem" => Some(("div", "poem")),
+        b"stanza" => Some(("div", "stanza")),
+        b"v" => Some(("p", "verse")),
+        b"subtitle" => Some(("p", "subtitle")),
+        b"text-author" => Some(("p", "author")),
+        _ => None,
+    }
+}
+
+/// Render the main body into the pages and the table of contents
+fn render(xml: &str, notes: &Refs, images: &Refs) -> anyhow::Result<(Vec<String>, Vec<TocEntry>)> {
+    let mut reader = Reader::from_str(xml);
+    let mut pages = Vec::new();
+    let mut toc = Vec::new();
+    let mut page = String::new();
+
+    let mut in_body = false;
+    let mut depth = 0;
+    let mut sections = 0;
+    let mut title: Option<String> = None;
+    let mut links: Vec<&'static str> = Vec::new();
+    // the number of the open HTML elements other than the sections
+    let mut open: usize = 0;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = e.local_name();
+                if !in_body {
+                    if name.as_ref() == b"body" && attribute(&e, b"name").is_none() {
+                        in_body = true;
+                    }
+                    continue;
+                }
+                // the full page is broken before the next section or paragraph
+                if page.len() >= PAGE_SIZE
+                    && open == 0
+                    && title.is_none()
+                    && name.as_ref() != b"title"
+                {
+                    break_page(&mut pages, &mut page, depth);
+                }
+                match name.as_ref() {
+                    b"section" => {
+                        depth += 1;
+                        sections += 1;
+                        page.push_str(&format!(r#"<div class="section" id="s{sections}">"#));
+                    }
+                    b"title" => {
+                        title = Some(String::new());
+                        let level = (depth + 1).min(6);
+                        page.push_str(&format!("<h{level}>"));
+                    }
+                    b"p" if title.is_some() => {
+                        if title.as_ref().is_some_and(|t| !t.trim().is_empty()) {
+                            page.push_str("<br/>");
+                        }
+                    }
+                    b"a" => {
+                        let href = attribute(&e, b"href").unwrap_or_default();
+                        let note = href.strip_prefix('#').and_then(|id| notes.get(id));
+                        if let Some(note) = note {
+                            page.push_str(&format!(
+                                r#"<a class="note" href="{}" data-note="{}">"#,
+                                escape(&href),
+                                escape(note)
+                            ));
+                            links.push("a");
+                        } else if href.starts_with("http") {
+                            page.push_str(&format!(r#"<a href="{}">"#, escape(&href)));
+                            links.push("a");
+                        } else {
+                            page.push_str("<span>");
+                            links.push("span");
+                        }
+                    }
+                    b"image" => push_image(&mut page, &e, images),
+                    other => {
+                        if let Some(tag) = html_tag(other) {
+                            page.push_str(&format!("<{tag}>"));
+                            open += 1;
+                        } else if let Some((tag, class)) = html_class(other) {
+                            page.push_str(&format!(r#"<{tag} class="{class}">"#));
+                            open += 1;
+                        }
+                    }
+                }
+            }
+            Event::Empty(e) if in_body => {
+                if page.len() >= PAGE_SIZE && open == 0 && title.is_none() {
+                    break_page(&mut pages, &mut page, depth);
+                }
+                match e.local_name().as_ref() {
+                    b"image" => push_image(&mut page, &e, images),
+                    b"empty-line" => page.push_str("<br/>"),
+                    _ => {}
+                }
+            }
+            Event::End(e) if in_body => match e.local_name().as_ref() {
+                b"body" => break,
+                b"section" => {
+                    depth = depth.saturating_sub(1);
+                    page.push_str("</div>");
+                }
+                b"title" => {
+                    let level = (depth + 1).min(6);
+                    page.push_str(&format!("</h{level}>"));
+                    if let Some(text) = title.take() {
+                        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                        if depth > 0 && !text.is_empty() {
+                            toc.push(TocEntry {
+                                title: text,
+                                level: depth,
+                                page: pages.len(),
+                                anchor: format!("s{sections}"),
+                            });
+                        }
+                    }
+                }
+                b"p" if title.is_some() => {}
+                b"a" => {
+                    let tag = links.pop().unwrap_or("span");
+                    page.push_str(&format!("</{tag}>"));
+                }
+                other => {
+                    if let Some(tag) = html_tag(other) {
+                        page.push_str(&format!("</{tag}>"));
+                        open = open.saturating_sub(1);
+                    } else if let Some((tag, _)) = html_class(other) {
+                        page.push_str(&format!("</{tag}>"));
+                        open = open.saturating_sub(1);
+                    }
+                }
+            },
+            Event::Text(e) if in_body => {
+                let text = e.unescape()?;
+                if let Some(title) = title.as_mut() {
+                    title.push_str(&text);
+                    title.push(' ');
+                }
+                page.push_str(&escape(&text));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if !page.is_empty() || pages.is_empty() {
+        pages.push(page);
+    }
+    Ok((pages, toc))
+}
+
+/// Close the sections open on the full page and reopen them on the next one
+fn break_page(pages: &mut Vec<String>, page: &mut String, depth: usize) {
+    page.push_str(&"</div>".repeat(depth));
+    pages.push(std::mem::take(page));
+    page.push_str(&r#"<div class="section">"#.repeat(depth));
+}
+
+/// Returns the `data:` URI of the binary, None if it is not an image in base64
+fn image_src(ctype: &str, data: &str) -> Option<String> {
+    let subtype = ctype.trim().to_lowercase();
+    let subtype = subtype.strip_prefix("image/")?;
+    let valid_type = !subtype.is_empty()
+        && subtype
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c));
+    let valid_data = data
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c));
+    match valid_type && valid_data {
+        true => Some(format!("data:image/{subtype};base64,{data}")),
+        false => None,
+    }
+}
+
+fn push_image(page: &mut String, e: &BytesStart, images: &Refs) {
+    let href = attribute(e, b"href").unwrap_or_default();
+    if let Some(src) = href.strip_prefix('#').and_then(|id| images.get(id)) {
+        page.push_str(&format!(r#"<img src="{}" alt=""/>"#, escape(src)));
+    }
+}
+
+const STYLE: &str = r#"
+body { max-width: 40em; margin: 0 auto; padding: 0 1em; font-family: serif; line-height: 1.5; }
+nav { display: flex; justify-content: space-between; margin: 1em 0; font-family: sans-serif; }
+img { max-width: 100%; }
+p { text-indent: 1.5em; margin: 0.3em 0; }
+.subtitle, .author { text-align: center; text-indent: 0; }
+.epigraph { font-style: italic; margin-left: 40%; }
+.verse { text-indent: 0; margin-left: 2em; }
+.stanza { margin: 1em 0; }
+a.note { vertical-align: super; font-size: smaller; }
+nav form { margin: 0; }
+nav button { font: inherit; color: #00e; background: none; border: none; padding: 0; cursor: pointer; }
+#popup { display: none; position: fixed; left: 1em; right: 1em; bottom: 1em; max-width: 38em; margin: 0 auto;
+         padding: 1em; background: #ffd; border: 1px solid #aa8; font-size: smaller; }
+"#;
+
+const SCRIPT: &str = r#"
+document.addEventListener('click', function (e) {
+    var popup = document.getElementById('popup');
+    var note = e.target.closest('a.note');
+    if (note) {
+        e.preventDefault();
+        popup.textContent = note.dataset.note;
+        popup.style.display = 'block';
+    } else {
+        popup.style.display = 'none';
+    }
+});
+"#;
+
+/// Wrap the content into the HTML document with the navigation above and below it
+pub fn document(title: &str, nav: &str, content: &str) -> String {
+    let title = escape(title);
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8"/>
+<meta name="viewport" content="width=device-width, initial-scale=1"/>
+<title>{title}</title>
+<style>{STYLE}</style>
+</head>
+<body>
+{nav}
+{content}
+{nav}
+<div id="popup"></div>
+<script>{SCRIPT}</script>
+</body>
+</html>"#
+    )
+}
+
+/// Render the page with the number `num` (starts from 1) as the HTML document
+pub fn format_page(book: &Book, id: u32, num: usize, urls: &Urls) -> String {
+    let total = book.pages.len();
+    let num = num.clamp(1, total);
+    let page = |n: usize| urls.url(&format!("/read/{id}/page/{n}"));
+    let prev = if num > 1 {
+        format!(r#"<a href="{}">&larr;</a>"#, page(num - 1))
+    } else {
+        String::from("<span></span>")
+    };
+    let next = if num < total {
+        format!(r#"<a href="{}">&rarr;</a>"#, page(num + 1))
+    } else {
+        String::from("<span></span>")
+    };
+    let toc = urls.url(&format!("/read/{id}/toc"));
+    let fb2 = urls.url(&format!("/opds/book/id/{id}"));
+    let send = urls.url(&format!("/read/{id}/send"));
+    let nav = format!(
+        r#"<nav>{prev}<a href="{toc}">{num} / {total}</a><a href="{fb2}">fb2</a><form method="post" action="{send}"><button title="e-mail">&#9993;</button></form>{next}</nav>"#
+    );
+    document(&book.title, &nav, &book.pages[num - 1])
+}
+
+/// Render the table of contents as the HTML document
+pub fn format_toc(book: &Book, id: u32, urls: &Urls) -> String {
+    let page = |n: usize| urls.url(&format!("/read/{id}/page/{n}"));
+    let mut content = format!("<h1>{}</h1>\n<ul>\n", escape(&book.title));
+    for entry in &book.toc {
+        content.push_str(&format!(
+            r#"<li style="margin-left: {}em"><a href="{}#{}">{}</a></li>"#,
+            entry.level - 1,
+            page(entry.page + 1),
+            entry.anchor,
+            escape(&entry.title)
+        ));
+        content.push('\n');
+    }
+    content.push_str("</ul>");
+    let nav = format!(
+        r#"<nav><a href="{}">1 / {}</a></nav>"#,
+        page(1),
+        book.pages.len()
+    );
+    document(&book.title, &nav, &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the FB2 document with the sections of the paragraphs of `size` bytes
+    fn fb2(sections: &[&str], size: usize) -> String {
+        let text = "Текст ".repeat(size / "Текст ".len());
+        let mut body = String::new();
+        for title in sections {
+            body.push_str(&format!(
+                "<section><title><p>{title}</p></title><p>{text}</p></section>"
+            ));
+        }
+        format!(
+            r##"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook xmlns:l="http://www.w3.org/1999/xlink">
+<description><title-info><book-title>Книга &amp; мир</book-title></title-info></description>
+<body>{body}<section><p>Сноска<a l:href="#n1">1</a></p><image l:href="#img"/><image l:href="#bad"/></section></body>
+<body name="notes"><section id="n1"><title><p>1</p></title><p>Текст сноски</p></section></body>
+<binary id="img" content-type="image/png">iVBO
+Rw0K</binary>
+<binary id="bad" content-type="image/png&quot; onerror=&quot;alert(1)">AAAA</binary>
+</FictionBook>"##
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        let book = parse(&fb2(&["Глава 1", "Глава 2"], 100)).unwrap();
+        assert_eq!("Книга & мир", book.title);
+        assert_eq!(1, book.pages.len());
+        let page = &book.pages[0];
+        assert!(page.contains("<h2>Глава 1</h2>"));
+        assert!(page.contains(r#"data-note="Текст сноски""#));
+        assert!(page.contains(r#"<img src="data:image/png;base64,iVBORw0K" alt=""/>"#));
+        assert!(!page.contains("onerror"));
+    }
+
+    #[test]
+    fn test_pages_and_toc() {
+        let book = parse(&fb2(&["Глава 1", "Глава 2", "Глава 3"], PAGE_SIZE)).unwrap();
+        // the section starts the new page when the page is full
+        assert_eq!(4, book.pages.len());
+        let toc = book
+            .toc
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.level, entry.page))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![("Глава 1", 1, 0), ("Глава 2", 1, 1), ("Глава 3", 1, 2)],
+            toc
+        );
+        assert_eq!("s2", book.toc[1].anchor);
+        assert!(book.pages[1].starts_with(r#"<div class="section" id="s2">"#));
+        assert!(book.pages[3].contains("Сноска"));
+    }
+
+    #[test]
+    fn test_long_section() {
+        let text = "Текст ".repeat(PAGE_SIZE / 4 / "Текст ".len());
+        let paragraphs = format!("<p>{text}</p>").repeat(10);
+        let xml = format!(
+            "<FictionBook><body><section><section><title><p>Глава</p></title><cite>{paragraphs}</cite>{paragraphs}</section></section></body></FictionBook>"
+        );
+        let book = parse(&xml).unwrap();
+        // the page is broken between the paragraphs of the section, not inside the cite
+        assert_eq!(4, book.pages.len());
+        for page in book.pages.iter() {
+            assert_eq!(page.matches("<div").count(), page.matches("</div>").count());
+            assert_eq!(
+                page.matches("<blockquote").count(),
+                page.matches("</blockquote>").count()
+            );
+        }
+        assert!(book.pages[1].starts_with(r#"<div class="section"><div class="section"><p>"#));
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = Cache::new(2);
+        let load = |title: &str| {
+            let title = String::from(title);
+            move || {
+                Ok(Book {
+                    title,
+                    pages: vec![],
+                    toc: vec![],
+                })
+            }
+        };
+        cache.get_or_load(1, load("1")).unwrap();
+        cache.get_or_load(2, load("2")).unwrap();
+        // the cached book is not loaded again and becomes the most recently read
+        let book = cache.get_or_load(1, || anyhow::bail!("loaded")).unwrap();
+        assert_eq!("1", book.title);
+        cache.get_or_load(3, load("3")).unwrap();
+        assert!(cache.get_or_load(2, || anyhow::bail!("loaded")).is_err());
+        assert_eq!("1", cache.get_or_load(1, load("x")).unwrap().title);
+    }
+
+    #[test]
+    fn test_image_src() {
+        assert_eq!(
+            Some(String::from("data:image/svg+xml;base64,PHN2Zz4=")),
+            image_src("image/SVG+XML", "PHN2Zz4=")
+        );
+        assert_eq!(None, image_src("text/html", "AAAA"));
+        assert_eq!(None, image_src("image/png\" x=\"", "AAAA"));
+        assert_eq!(None, image_src("image/png", "AA\"AA"));
+    }
+}
@@ -1,33 +1,79 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
+/// The separator of the spellings of the same prefix in the mask like `Се|Сё`
+pub const SEPARATOR: char = '|';
 
+/// Returns the form of the name in which the names and the prefixes are compared:
+/// NFC, lowercase, `ё` as `е`, the latin letters without diacritics, the punctuation
+/// and the spaces collapsed into a single space, the leading ones are skipped
+pub fn normalize(value: &str) -> String {
+    let mut normalized = String::with_capacity(value.len());
+    for c in value.nfc().flat_map(char::to_lowercase) {
+        if is_combining_mark(c) {
+            continue;
+        } else if c == 'ё' {
+            normalized.push('е');
+        } else if is_cyrillic(c) {
+            normalized.push(c);
+        } else if c.is_alphanumeric() {
+            let base = [c].into_iter().nfd().filter(|c| !is_combining_mark(*c));
+            normalized.extend(base);
+        } else if !normalized.is_empty() && !normalized.ends_with(' ') {
+            normalized.push(' ');
+        }
+    }
+    normalized
+}
+
+/// Returns true if the char is Cyrillic, the letters like `й` and `ї` are kept by
+/// `normalize`, they are not the letters with diacritics
+pub fn is_cyrillic(c: char) -> bool {
+    ('\u{0400}'..='\u{04FF}').contains(&c)
+}
+
+/// Returns the first spelling of the mask to show it
+pub fn first_variant(mask: &str) -> &str {
+    mask.split(SEPARATOR).next().unwrap_or_default()
+}
+
+/// Drill down the prefixes starting from the mask while there is the only way.
+/// The mask may have several spellings separated by `|`, the prefixes which are equal
+/// after `normalize` are merged and followed together. Returns the complete names
+/// and the prefixes to choose from, the merged prefixes are joined by `|`.
 pub fn search_by_mask<F, S>(mask: S, fetcher: F) -> anyhow::Result<(Vec<String>, Vec<String>)>
 where
     F: Fn(&String) -> anyhow::Result<Vec<String>>,
     S: Into<String>,
 {
-    let mut mask = mask.into();
+    let mut masks = mask
+        .into()
+        .split(SEPARATOR)
+        .map(String::from)
+        .collect::<Vec<_>>();
     let mut complete = Vec::new();
     let mut incomplete = Vec::new();
 
     loop {
-        let patterns = fetcher(&mask)?;
-        let (mut exact, mut tail) = patterns.into_iter().partition(|curr| mask.eq(curr));
+        let mut patterns = Vec::new();
+        for mask in masks.iter() {
+            for pattern in fetcher(mask)? {
+                if !patterns.contains(&pattern) {
+                    patterns.push(pattern);
+                }
+            }
+        }
+        let (mut exact, tail): (Vec<_>, Vec<_>) =
+            patterns.into_iter().partition(|curr| masks.contains(curr));
         complete.append(&mut exact);
 
-        if tail.is_empty() {
+        let mut buckets = group_prefixes(tail);
+        if buckets.is_empty() {
             break;
-        } else if 1 == tail.len() {
-            std::mem::swap(&mut mask, &mut tail[0]);
-        } else if 2 == tail.len() {
-            let are_equal = tail[0].to_lowercase() == tail[1].to_lowercase();
-            if are_equal {
-                std::mem::swap(&mut mask, &mut tail[0]);
-            } else {
-                incomplete.append(&mut tail);
-                break;
-            }
+        } else if 1 == buckets.len() {
+            masks = buckets.remove(0);
         } else {
-            incomplete.append(&mut tail);
+            incomplete.extend(buckets.iter().map(|variants| join(variants)));
             break;
         }
     }
@@ -35,6 +81,31 @@ where
     Ok((complete, incomplete))
 }
 
+/// Returns the prefixes merged by `normalize` keeping the order, the spellings
+/// of the same prefix are joined by `|`
+pub fn merge_prefixes(prefixes: Vec<String>) -> Vec<String> {
+    group_prefixes(prefixes)
+        .iter()
+        .map(|variants| join(variants))
+        .collect()
+}
+
+fn group_prefixes(prefixes: Vec<String>) -> Vec<Vec<String>> {
+    let mut buckets: Vec<(String, Vec<String>)> = Vec::new();
+    for prefix in prefixes.into_iter() {
+        let key = normalize(&prefix);
+        match buckets.iter_mut().find(|(curr, _)| *curr == key) {
+            Some((_, variants)) => variants.push(prefix),
+            None => buckets.push((key, vec![prefix])),
+        }
+    }
+    buckets.into_iter().map(|(_, variants)| variants).collect()
+}
+
+fn join(variants: &[String]) -> String {
+    variants.join(SEPARATOR.encode_utf8(&mut [0; 4]))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -48,8 +119,21 @@ mod tests {
             "BBBB" => vec!["BBBB"],
             "C" => vec!["CC", "cc"],
             "CC" => vec!["CCC", "ccc"],
+            "cc" => vec!["CCC", "ccc"],
             "CCC" => vec!["CCC", "ccc"],
             "ccc" => vec!["ccc"],
+            "С" => vec!["Се", "Сё", "СЕ", "Си"],
+            "Се" => vec!["Сем"],
+            "Сё" => vec!["Сём"],
+            "СЕ" => vec!["Сем"],
+            "Сем" => vec!["Семин"],
+            "Сём" => vec!["Сёмин"],
+            "Семин" => vec!["Семин"],
+            "Сёмин" => vec!["Сёмин"],
+            " " => vec!["  Д", "\"Д", "Д"],
+            "  Д" => vec!["  Д"],
+            "\"Д" => vec!["\"Д"],
+            "Д" => vec!["Д"],
             _ => vec![],
         };
         if out.is_empty() {
@@ -96,4 +180,36 @@ mod tests {
         assert_eq!(empty, tail.iter().map(|a| a.as_str()).collect::<Vec<_>>());
         Ok(())
     }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!("семин", normalize("СЁМИН"));
+        assert_eq!("семин", normalize("Се\u{0308}мин"));
+        assert_eq!("стругацкий", normalize("Стругацкий"));
+        assert_eq!("сент экзюпери", normalize(" «Сент-Экзюпери"));
+        assert_eq!("celine", normalize("Céline"));
+        assert_eq!("o neill", normalize("O’Neill"));
+    }
+
+    #[test]
+    fn test_merged_prefixes() -> anyhow::Result<()> {
+        let (exact, tail) = search_by_mask("С", fetcher)?;
+        assert!(exact.is_empty());
+        assert_eq!(vec!["Се|Сё|СЕ", "Си"], tail);
+
+        // the variants of the bucket are followed together
+        let (exact, tail) = search_by_mask("Се|Сё|СЕ", fetcher)?;
+        assert_eq!(vec!["Семин", "Сёмин"], exact);
+        assert!(tail.is_empty());
+        assert_eq!("Се", first_variant("Се|Сё|СЕ"));
+        let prefixes = vec!["А", "Б", "а", " ", "«"];
+        let prefixes = prefixes.into_iter().map(String::from).collect();
+        assert_eq!(vec!["А|а", "Б", " |«"], merge_prefixes(prefixes));
+
+        // the leading spaces and quotes are skipped
+        let (exact, tail) = search_by_mask(" ", fetcher)?;
+        assert_eq!(vec!["  Д", "\"Д", "Д"], exact);
+        assert!(tail.is_empty());
+        Ok(())
+    }
 }
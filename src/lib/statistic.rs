@@ -1,7 +1,67 @@
 use log::{debug, error};
 use rusqlite::Connection;
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fmt;
+
+/// The state of the mail in the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailStatus {
+    Queued,
+    Sent,
+    Failed,
+}
+impl MailStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MailStatus::Queued => "queued",
+            MailStatus::Sent => "sent",
+            MailStatus::Failed => "failed",
+        }
+    }
+}
+impl From<&str> for MailStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "sent" => MailStatus::Sent,
+            "failed" => MailStatus::Failed,
+            _ => MailStatus::Queued,
+        }
+    }
+}
+impl fmt::Display for MailStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The book queued to be sent by e-mail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+    pub id: u32,
+    pub book_id: u32,
+    pub address: String,
+    pub status: MailStatus,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub updated: String,
+}
+impl Mail {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Mail {
+            id: row.get(0)?,
+            book_id: row.get(1)?,
+            address: row.get(2)?,
+            status: MailStatus::from(row.get::<_, String>(3)?.as_str()),
+            attempts: row.get(4)?,
+            error: row.get(5)?,
+            updated: row.get(6)?,
+        })
+    }
+}
+
+const MAIL_COLUMNS: &str = "id, book_id, address, status, attempts, error, updated";
 
 #[derive(Debug)]
 pub struct StatisticApi {
@@ -17,9 +77,30 @@ impl StatisticApi {
         let sql = "INSERT INTO downloads VALUES($1, datetime('now', 'localtime'));";
         let mut statement = self.conn.prepare_cached(sql)?;
         let _ = statement.execute([id])?;
+
+        let sql = r#"
+            INSERT INTO popularity VALUES($1, 1)
+            ON CONFLICT(book_id) DO UPDATE SET count = count + 1;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let _ = statement.execute([id])?;
         Ok(())
     }
 
+    /// Returns the number of downloads of the books which were downloaded at least once
+    pub fn downloads_by_books_ids(&self, ids: &[u32]) -> anyhow::Result<HashMap<u32, u32>> {
+        let sql = "SELECT count FROM popularity WHERE book_id = $1;";
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let mut downloads = HashMap::new();
+        for &id in ids {
+            let mut rows = statement.query_map([id], |row| row.get(0))?;
+            if let Some(count) = rows.next().transpose()? {
+                downloads.insert(id, count);
+            }
+        }
+        Ok(downloads)
+    }
+
     pub fn load_last(&self, days: u8) -> anyhow::Result<Vec<u32>> {
         let days = format!("-{days} days");
         let sql = "SELECT book_id AS id FROM downloads WHERE DATE(downloaded) >= DATE('now', $1);";
@@ -34,6 +115,134 @@ impl StatisticApi {
         Ok(ids)
     }
 
+    /// Returns true if the book was downloaded
+    pub fn is_downloaded(&self, book_id: u32) -> anyhow::Result<bool> {
+        let sql = "SELECT EXISTS(SELECT 1 FROM downloads WHERE book_id = $1);";
+        let mut statement = self.conn.prepare_cached(sql)?;
+        Ok(statement.query_row([book_id], |row| row.get(0))?)
+    }
+
+    /// Save the last page read in the built-in reader
+    pub fn save_position(&self, id: u32, page: u32) -> anyhow::Result<()> {
+        let sql = "INSERT INTO positions VALUES($1, $2, datetime('now', 'localtime'));";
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let _ = statement.execute([id, page])?;
+        Ok(())
+    }
+
+    /// Load the last page read in the built-in reader
+    pub fn load_position(&self, id: u32) -> anyhow::Result<Option<u32>> {
+        let sql = "SELECT page FROM positions WHERE book_id = $1;";
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let mut rows = statement.query_map([id], |row| row.get(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Save the setting of the user, the empty value removes the setting
+    pub fn save_setting(&self, user: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        if value.is_empty() {
+            let sql = "DELETE FROM settings WHERE user = $1 AND name = $2;";
+            let mut statement = self.conn.prepare_cached(sql)?;
+            let _ = statement.execute([user, name])?;
+        } else {
+            let sql = "INSERT INTO settings VALUES($1, $2, $3);";
+            let mut statement = self.conn.prepare_cached(sql)?;
+            let _ = statement.execute([user, name, value])?;
+        }
+        Ok(())
+    }
+
+    /// Load the setting of the user
+    pub fn load_setting(&self, user: &str, name: &str) -> anyhow::Result<Option<String>> {
+        let sql = "SELECT value FROM settings WHERE user = $1 AND name = $2;";
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let mut rows = statement.query_map([user, name], |row| row.get(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Put the book into the mail queue, returns the id of the mail
+    pub fn queue_mail(&self, book_id: u32, address: &str) -> anyhow::Result<u32> {
+        let sql = r#"
+            INSERT INTO mails(book_id, address, status, attempts, next_try, updated)
+            VALUES($1, $2, 'queued', 0, datetime('now', 'localtime'), datetime('now', 'localtime'));
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let _ = statement.execute(rusqlite::params![book_id, address])?;
+        Ok(self.conn.last_insert_rowid() as u32)
+    }
+
+    /// Returns true if the book is queued to the address or was sent to it
+    /// in the last `minutes`
+    pub fn has_recent_mail(
+        &self,
+        book_id: u32,
+        address: &str,
+        minutes: u32,
+    ) -> anyhow::Result<bool> {
+        let sql = r#"
+            SELECT COUNT(*) FROM mails
+            WHERE book_id = $1 AND address = $2
+              AND (status = 'queued'
+                OR status = 'sent' AND updated >= datetime('now', 'localtime', $3));
+        "#;
+        let since = format!("-{minutes} minutes");
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let count: u32 =
+            statement.query_row(rusqlite::params![book_id, address, since], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    /// Returns the queued mails which are due to be sent
+    pub fn pending_mails(&self) -> anyhow::Result<Vec<Mail>> {
+        let sql = format!(
+            "SELECT {MAIL_COLUMNS} FROM mails
+             WHERE status = 'queued' AND next_try <= datetime('now', 'localtime')
+             ORDER BY id;"
+        );
+        let mut statement = self.conn.prepare_cached(&sql)?;
+        let rows = statement.query_map([], Mail::from_row)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Returns the last mails sent to the address, the newest first
+    pub fn mails(&self, address: &str, limit: u32) -> anyhow::Result<Vec<Mail>> {
+        let sql = format!(
+            "SELECT {MAIL_COLUMNS} FROM mails WHERE address = $1 ORDER BY id DESC LIMIT $2;"
+        );
+        let mut statement = self.conn.prepare_cached(&sql)?;
+        let rows = statement.query_map(rusqlite::params![address, limit], Mail::from_row)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Mark the mail as sent
+    pub fn mail_sent(&self, id: u32) -> anyhow::Result<()> {
+        let sql = r#"
+            UPDATE mails SET status = 'sent', attempts = attempts + 1, error = NULL,
+                updated = datetime('now', 'localtime')
+            WHERE id = $1;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let _ = statement.execute([id])?;
+        Ok(())
+    }
+
+    /// Record the failed attempt, the mail is retried after `retry` seconds or marked as failed
+    pub fn mail_failed(&self, id: u32, error: &str, retry: Option<u64>) -> anyhow::Result<()> {
+        let (status, delay) = match retry {
+            Some(secs) => (MailStatus::Queued, format!("+{secs} seconds")),
+            None => (MailStatus::Failed, String::from("+0 seconds")),
+        };
+        let sql = r#"
+            UPDATE mails SET status = $1, attempts = attempts + 1, error = $2,
+                next_try = datetime('now', 'localtime', $3),
+                updated = datetime('now', 'localtime')
+            WHERE id = $4;
+        "#;
+        let mut statement = self.conn.prepare_cached(sql)?;
+        let _ = statement.execute(rusqlite::params![status.as_str(), error, delay, id])?;
+        Ok(())
+    }
+
     /// Returns true if database opened in ReadOnly
     pub fn is_readonly(&self) -> anyhow::Result<bool> {
         Ok(self.conn.is_readonly(rusqlite::DatabaseName::Main)?)
@@ -51,6 +260,30 @@ impl TryFrom<&str> for StatisticApi {
             book_id     INTEGER NOT NULL,
             downloaded  DATETIME DEFAULT CURRENT_TIMESTAMP,
             UNIQUE(book_id) ON CONFLICT REPLACE);
+        CREATE TABLE IF NOT EXISTS positions(
+            book_id     INTEGER NOT NULL,
+            page        INTEGER NOT NULL,
+            updated     DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(book_id) ON CONFLICT REPLACE);
+        CREATE TABLE IF NOT EXISTS settings(
+            user        TEXT NOT NULL,
+            name        TEXT NOT NULL,
+            value       TEXT NOT NULL,
+            UNIQUE(user, name) ON CONFLICT REPLACE);
+        CREATE TABLE IF NOT EXISTS mails(
+            id          INTEGER PRIMARY KEY,
+            book_id     INTEGER NOT NULL,
+            address     TEXT NOT NULL,
+            status      TEXT NOT NULL,
+            attempts    INTEGER NOT NULL DEFAULT 0,
+            error       TEXT,
+            next_try    DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated     DATETIME DEFAULT CURRENT_TIMESTAMP);
+        CREATE TABLE IF NOT EXISTS popularity(
+            book_id     INTEGER PRIMARY KEY,
+            count       INTEGER NOT NULL DEFAULT 0);
+        INSERT OR IGNORE INTO popularity
+            SELECT book_id, COUNT(*) FROM downloads GROUP BY book_id;
         "#,
         )?;
 
@@ -66,3 +299,57 @@ impl TryFrom<&String> for StatisticApi {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mail_queue() {
+        let stat = StatisticApi::try_from(":memory:").unwrap();
+        stat.save_setting("bob", "email", "bob@kindle.com").unwrap();
+        let address = stat.load_setting("bob", "email").unwrap().unwrap();
+        assert_eq!(None, stat.load_setting("", "email").unwrap());
+
+        let first = stat.queue_mail(5, &address).unwrap();
+        let second = stat.queue_mail(7, &address).unwrap();
+        assert_eq!(2, stat.pending_mails().unwrap().len());
+        assert!(stat.has_recent_mail(5, &address, 60).unwrap());
+        assert!(!stat.has_recent_mail(5, "alice@kindle.com", 60).unwrap());
+
+        stat.mail_sent(first).unwrap();
+        stat.mail_failed(second, "refused", Some(60)).unwrap();
+        assert!(stat.pending_mails().unwrap().is_empty());
+        assert!(stat.has_recent_mail(5, &address, 60).unwrap());
+        stat.conn
+            .execute(
+                "UPDATE mails SET updated = datetime('now', 'localtime', '-2 hours');",
+                [],
+            )
+            .unwrap();
+        assert!(!stat.has_recent_mail(5, &address, 60).unwrap());
+
+        let mails = stat.mails(&address, 10).unwrap();
+        assert_eq!(MailStatus::Queued, mails[0].status);
+        assert_eq!(Some(String::from("refused")), mails[0].error);
+        assert_eq!(1, mails[0].attempts);
+        assert_eq!(MailStatus::Sent, mails[1].status);
+
+        stat.mail_failed(second, "refused", None).unwrap();
+        let mails = stat.mails(&address, 1).unwrap();
+        assert_eq!(MailStatus::Failed, mails[0].status);
+    }
+
+    #[test]
+    fn test_downloads() {
+        let stat = StatisticApi::try_from(":memory:").unwrap();
+        stat.save(1).unwrap();
+        stat.save(1).unwrap();
+        stat.save(2).unwrap();
+        let downloads = stat.downloads_by_books_ids(&[1, 2, 3]).unwrap();
+        assert_eq!(2, downloads[&1]);
+        assert_eq!(1, downloads[&2]);
+        assert!(!downloads.contains_key(&3));
+        assert!(stat.is_downloaded(2).unwrap());
+        assert!(!stat.is_downloaded(3).unwrap());
+    }
+}
@@ -0,0 +1,79 @@
+//! HTTPS support with rustls.
+//!
+//! The certificate is resolved for every handshake from the shared slot,
+//! so it can be reloaded from the files without restarting the server.
+use log::{error, info};
+use rustls::crypto::ring;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Read the certificate chain and the private key from the PEM files
+fn load(cert: &Path, key: &Path) -> anyhow::Result<CertifiedKey> {
+    let mut reader = BufReader::new(File::open(cert)?);
+    let chain = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if chain.is_empty() {
+        anyhow::bail!("No certificates in {}", cert.display());
+    }
+
+    let mut reader = BufReader::new(File::open(key)?);
+    let Some(der) = rustls_pemfile::private_key(&mut reader)? else {
+        anyhow::bail!("No private key in {}", key.display());
+    };
+    let key = ring::sign::any_supported_type(&der)?;
+    let certified = CertifiedKey::new(chain, key);
+    // the files may be replaced one by one, the mismatched pair is not used
+    certified.keys_match()?;
+    Ok(certified)
+}
+
+#[derive(Debug)]
+pub struct CertResolver {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+impl CertResolver {
+    pub fn new(cert: PathBuf, key: PathBuf) -> anyhow::Result<Self> {
+        let current = RwLock::new(Arc::new(load(&cert, &key)?));
+        Ok(CertResolver { cert, key, current })
+    }
+
+    /// Returns paths of the certificate and the key files
+    pub fn files(&self) -> Vec<PathBuf> {
+        vec![self.cert.clone(), self.key.clone()]
+    }
+
+    /// Reload the certificate, the current one is kept on errors
+    pub fn reload(&self) {
+        match load(&self.cert, &self.key) {
+            Ok(certified) => {
+                if let Ok(mut current) = self.current.write() {
+                    *current = Arc::new(certified);
+                    info!("The certificate {} has been reloaded", self.cert.display());
+                }
+            }
+            Err(err) => error!("The certificate {}: {err}", self.cert.display()),
+        }
+    }
+}
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|current| current.clone())
+    }
+}
+
+/// Returns the server configuration using the resolver for certificates
+pub fn server_config(resolver: Arc<CertResolver>) -> anyhow::Result<ServerConfig> {
+    let provider = Arc::new(ring::default_provider());
+    let config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    Ok(config)
+}
@@ -0,0 +1,209 @@
+//! The transliteration of the Cyrillic names to search them with the Latin keyboard.
+//!
+//! The names and the queries are compared by their Latin keys: the Cyrillic text is
+//! transliterated by every scheme, the Latin text is kept as it is. So `strugackij`,
+//! `strugatskiy` and `strugatsky` find `Стругацкий`, and `Кинг` finds `King`.
+use crate::search;
+
+use std::collections::BTreeSet;
+
+/// The transliteration schemes of the Cyrillic letters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// GOST 7.79-2000 system B like `Strugaczkij`
+    Gost,
+    /// BGN/PCGN like `Strugatskiy`
+    Bgn,
+    /// The scholarly one without diacritics like `Strugackij`
+    Scholarly,
+}
+impl Scheme {
+    pub const ALL: [Scheme; 3] = [Scheme::Gost, Scheme::Bgn, Scheme::Scholarly];
+}
+
+/// Returns the Latin form of the lowercase Cyrillic letter, the other chars are kept
+fn letter(c: char, next: Option<char>, scheme: Scheme) -> Option<&'static str> {
+    use Scheme::*;
+    let latin = match (c, scheme) {
+        ('а', _) => "a",
+        ('б', _) => "b",
+        ('в', _) => "v",
+        ('г' | 'ґ', _) => "g",
+        ('д', _) => "d",
+        ('е' | 'ё' | 'э', _) => "e",
+        ('є', Scholarly) => "je",
+        ('є', _) => "ye",
+        ('ж', Scholarly) => "z",
+        ('ж', _) => "zh",
+        ('з', _) => "z",
+        ('и' | 'і', _) => "i",
+        ('ї', Scholarly) => "ji",
+        ('ї', _) => "yi",
+        ('й', Bgn) => "y",
+        ('й', _) => "j",
+        ('к', _) => "k",
+        ('л', _) => "l",
+        ('м', _) => "m",
+        ('н', _) => "n",
+        ('о', _) => "o",
+        ('п', _) => "p",
+        ('р', _) => "r",
+        ('с', _) => "s",
+        ('т', _) => "t",
+        ('у' | 'ў', _) => "u",
+        ('ф', _) => "f",
+        ('х', Bgn) => "kh",
+        ('х', _) => "x",
+        // GOST writes `c` before the vowels which are written as `e`, `i`, `y` and `j`
+        ('ц', Gost) => match next {
+            Some('е' | 'ё' | 'и' | 'і' | 'ы' | 'й' | 'є' | 'ї') => "c",
+            _ => "cz",
+        },
+        ('ц', Bgn) => "ts",
+        ('ц', Scholarly) => "c",
+        ('ч', Scholarly) => "c",
+        ('ч', _) => "ch",
+        ('ш', Scholarly) => "s",
+        ('ш', _) => "sh",
+        ('щ', Gost) => "shh",
+        ('щ', Bgn) => "shch",
+        ('щ', Scholarly) => "sc",
+        ('ъ' | 'ь', _) => "",
+        ('ы', _) => "y",
+        ('ю', Scholarly) => "ju",
+        ('ю', _) => "yu",
+        ('я', Scholarly) => "ja",
+        ('я', _) => "ya",
+        _ => return None,
+    };
+    Some(latin)
+}
+
+/// Returns the text transliterated by the scheme, the text is normalized like the masks
+pub fn to_latin(text: &str, scheme: Scheme) -> String {
+    let text = search::normalize(text);
+    let mut latin = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match letter(c, chars.peek().copied(), scheme) {
+            Some(letters) => latin.push_str(letters),
+            None => latin.push(c),
+        }
+    }
+    latin
+}
+
+/// Returns true if the text has the Cyrillic letters
+pub fn is_cyrillic(text: &str) -> bool {
+    text.chars().any(search::is_cyrillic)
+}
+
+/// Returns the Latin keys of the text. The endings like `-ij` and `-iy` are also
+/// written as `-y` like in the common spelling `Strugatsky`.
+fn keys(text: &str) -> Vec<String> {
+    let mut keys = match is_cyrillic(text) {
+        true => Scheme::ALL.iter().map(|s| to_latin(text, *s)).collect(),
+        false => vec![search::normalize(text)],
+    };
+    for idx in 0..keys.len() {
+        let short = keys[idx]
+            .split(' ')
+            .map(|word| match word.len() > 3 {
+                true => ["iy", "ij", "yj", "yy", "ii"]
+                    .iter()
+                    .find_map(|ending| word.strip_suffix(ending))
+                    .map(|stem| format!("{stem}y"))
+                    .unwrap_or_else(|| String::from(word)),
+                false => String::from(word),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        keys.push(short);
+    }
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Returns true if the name starts with the query in any transliteration
+pub fn matches(name: &str, query: &str) -> bool {
+    starts_with(&keys(name), &keys(query))
+}
+
+fn starts_with(names: &[String], queries: &[String]) -> bool {
+    queries
+        .iter()
+        .any(|query| names.iter().any(|name| name.starts_with(query.as_str())))
+}
+
+/// The names with their Latin keys sorted to find the names by the prefix of the key,
+/// the index is built once as the names are transliterated by every scheme
+#[derive(Debug, Default)]
+pub struct Index {
+    names: Vec<String>,
+    /// The keys with the positions of their names
+    keys: Vec<(String, usize)>,
+}
+impl Index {
+    pub fn new(names: Vec<String>) -> Self {
+        let mut index = Vec::new();
+        for (idx, name) in names.iter().enumerate() {
+            index.extend(keys(name).into_iter().map(|key| (key, idx)));
+        }
+        index.sort();
+        Index { names, keys: index }
+    }
+
+    /// Returns up to `limit` names which start with the query in any transliteration
+    /// written in the other script than the query, the names in the same script are
+    /// found by the masks. The names are returned in the order they are indexed.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<String> {
+        let cyrillic = is_cyrillic(query);
+        let mut found = BTreeSet::new();
+        for query in keys(query).iter().filter(|query| !query.trim().is_empty()) {
+            let start = self.keys.partition_point(|(key, _)| key < query);
+            let matched = self.keys[start..]
+                .iter()
+                .take_while(|(key, _)| key.starts_with(query.as_str()))
+                .map(|(_, idx)| *idx)
+                .filter(|idx| is_cyrillic(&self.names[*idx]) != cyrillic);
+            found.extend(matched);
+        }
+        found
+            .into_iter()
+            .take(limit)
+            .map(|idx| self.names[idx].clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_latin() {
+        assert_eq!("strugaczkij", to_latin("Стругацкий", Scheme::Gost));
+        assert_eq!("strugatskiy", to_latin("Стругацкий", Scheme::Bgn));
+        assert_eq!("strugackij", to_latin("Стругацкий", Scheme::Scholarly));
+        assert_eq!("cex", to_latin("Цех", Scheme::Gost));
+        assert_eq!("shchukin", to_latin("Щукин", Scheme::Bgn));
+        assert_eq!("ivan franko", to_latin("Іван Франко", Scheme::Bgn));
+    }
+
+    #[test]
+    fn test_search() {
+        for query in ["strugackij", "Strugatsky", "strugatskiy", "STRUGACZ"] {
+            assert!(matches("Стругацкий", query), "{query}");
+        }
+        assert!(matches("King", "Кинг"));
+        assert!(!matches("Стругацкий", "strugatz"));
+
+        let names = ["Стругацкий", "Strugatsky", "Струве", "Кинг"];
+        let index = Index::new(names.into_iter().map(String::from).collect());
+        assert_eq!(vec!["Стругацкий", "Струве"], index.search("stru", 10));
+        assert_eq!(vec!["Стругацкий"], index.search("stru", 1));
+        assert_eq!(vec!["Strugatsky"], index.search("Стругацк", 10));
+        assert!(index.search(" ", 10).is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+//! Building the public URLs of the server.
+//!
+//! The server may be mounted under a path prefix behind a reverse proxy, so all links
+//! are built from the base which is configured or taken from the `X-Forwarded-*` headers:
+//!  * `FB2S_BASE_URL=https://host/books` - all links are `https://host/books/opds/...`
+//!  * `FB2S_BASE_URL=/books` - all links are `/books/opds/...`
+//!  * `X-Forwarded-Prefix: /books` overrides the configured path prefix
+//!  * `X-Forwarded-Host` with `X-Forwarded-Proto` make the links absolute
+//!
+//! The headers are used only in the requests from the proxies of `FB2S_TRUSTED_PROXY`.
+use actix_web::dev::Payload;
+use actix_web::http::header::HeaderMap;
+use actix_web::{FromRequest, HttpRequest};
+
+use crate::user;
+
+use std::future::{ready, Ready};
+
+const FORWARDED_PROTO: &str = "x-forwarded-proto";
+const FORWARDED_HOST: &str = "x-forwarded-host";
+const FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+
+/// The configured public base URL or the path prefix
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BaseUrl {
+    origin: Option<String>,
+    prefix: String,
+}
+impl BaseUrl {
+    pub fn new(base: &str) -> Self {
+        let base = base.trim();
+        let (origin, path) = match base.split_once("://") {
+            Some((scheme, rest)) => {
+                let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                (Some(format!("{scheme}://{host}")), path)
+            }
+            None => (None, base),
+        };
+        BaseUrl {
+            origin,
+            prefix: normalize_prefix(path).unwrap_or_default(),
+        }
+    }
+}
+
+/// Returns the prefix like `/books` without the trailing slash or None if it is not valid
+fn normalize_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim().trim_end_matches('/');
+    let valid = prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/-_.~%".contains(c));
+    match valid && (prefix.is_empty() || prefix.starts_with('/')) {
+        true => Some(String::from(prefix)),
+        false => None,
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        // the proxies may append their values separated by commas
+        .and_then(|value| value.split(',').next())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// The builder of the public URLs for the request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Urls {
+    base: String,
+}
+impl Urls {
+    pub fn new<T: Into<String>>(base: T) -> Self {
+        Urls { base: base.into() }
+    }
+
+    /// Combine the configured base with the reverse proxy headers of the request
+    pub fn from_parts(config: &BaseUrl, headers: &HeaderMap, scheme: &str) -> Self {
+        let prefix = header(headers, FORWARDED_PREFIX)
+            .and_then(normalize_prefix)
+            .unwrap_or_else(|| config.prefix.clone());
+        let host = header(headers, FORWARDED_HOST).filter(|host| {
+            host.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-.:[]".contains(c))
+        });
+        let origin = match (&config.origin, host) {
+            (Some(origin), _) => Some(origin.clone()),
+            (None, Some(host)) => {
+                let proto = match header(headers, FORWARDED_PROTO) {
+                    Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+                    Some(_) => "http",
+                    None => scheme,
+                };
+                Some(format!("{proto}://{host}"))
+            }
+            (None, None) => None,
+        };
+        Urls::new(origin.unwrap_or_default() + &prefix)
+    }
+
+    /// Returns the public URL of the server path like `/opds/authors`,
+    /// other references (absolute URLs, anchors) are returned as is
+    pub fn url(&self, path: &str) -> String {
+        if path.starts_with('/') && !path.starts_with("//") {
+            format!("{}{path}", self.base)
+        } else {
+            String::from(path)
+        }
+    }
+}
+
+impl From<&HttpRequest> for Urls {
+    fn from(req: &HttpRequest) -> Self {
+        let default = BaseUrl::default();
+        let config = req.app_data::<BaseUrl>().unwrap_or(&default);
+        if !user::from_trusted_proxy(req) {
+            // the scheme is used only with the forwarded host
+            return Urls::from_parts(config, &HeaderMap::new(), "http");
+        }
+        let scheme = req.connection_info().scheme().to_string();
+        Urls::from_parts(config, req.headers(), &scheme)
+    }
+}
+
+/// The URLs are built from the configured `BaseUrl` and the request headers
+impl FromRequest for Urls {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Urls::from(req)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::TrustedProxies;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+    use actix_web::test::TestRequest;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn test_config() {
+        let none = HeaderMap::new();
+        let urls = Urls::from_parts(&BaseUrl::default(), &none, "http");
+        assert_eq!("/opds", urls.url("/opds"));
+
+        let urls = Urls::from_parts(&BaseUrl::new("/books/"), &none, "http");
+        assert_eq!("/books/opds", urls.url("/opds"));
+
+        let urls = Urls::from_parts(&BaseUrl::new("https://host/books"), &none, "http");
+        assert_eq!("https://host/books/opds", urls.url("/opds"));
+        assert_eq!("#note", urls.url("#note"));
+        assert_eq!("http://lib.ru/", urls.url("http://lib.ru/"));
+    }
+
+    #[test]
+    fn test_forwarded() {
+        let forwarded = headers(&[
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "example.com"),
+            ("x-forwarded-prefix", "/books/"),
+        ]);
+        let urls = Urls::from_parts(&BaseUrl::default(), &forwarded, "http");
+        assert_eq!("https://example.com/books/opds", urls.url("/opds"));
+
+        let urls = Urls::from_parts(&BaseUrl::new("http://lan:8080"), &forwarded, "http");
+        assert_eq!("http://lan:8080/books/opds", urls.url("/opds"));
+
+        let spoofed = headers(&[("x-forwarded-host", "evil\"><x")]);
+        let urls = Urls::from_parts(&BaseUrl::new("/books"), &spoofed, "http");
+        assert_eq!("/books/opds", urls.url("/opds"));
+    }
+
+    #[test]
+    fn test_untrusted_peer() {
+        let request = |peer: &str| {
+            TestRequest::default()
+                .peer_addr(peer.parse().unwrap())
+                .app_data(TrustedProxies::new("127.0.0.1"))
+                .insert_header(("x-forwarded-host", "example.com"))
+                .insert_header(("x-forwarded-prefix", "/books"))
+                .to_http_request()
+        };
+        let urls = Urls::from(&request("127.0.0.1:4000"));
+        assert_eq!("http://example.com/books/opds", urls.url("/opds"));
+        let urls = Urls::from(&request("10.0.0.1:4000"));
+        assert_eq!("/opds", urls.url("/opds"));
+    }
+}
@@ -0,0 +1,110 @@
+//! The user of the request.
+//!
+//! The server has no accounts, the user name is provided by the authenticating
+//! reverse proxy in the `X-Remote-User` or `X-Forwarded-User` header.
+//! The headers are trusted only in the requests from the proxies listed in
+//! `FB2S_TRUSTED_PROXY` like `127.0.0.1,::1`, any client may send them directly.
+//! The requests without the header share the settings of the anonymous user.
+//! The same proxies are trusted with the `X-Forwarded-*` headers of the public URLs
+//! and the client address.
+use actix_web::dev::Payload;
+use actix_web::http::header::HeaderMap;
+use actix_web::{FromRequest, HttpRequest};
+use log::warn;
+
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+
+const USER_HEADERS: [&str; 2] = ["x-remote-user", "x-forwarded-user"];
+const MAX_USER_LEN: usize = 128;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct User(pub String);
+impl User {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        USER_HEADERS
+            .iter()
+            .filter_map(|name| headers.get(*name))
+            .filter_map(|value| value.to_str().ok())
+            .map(str::trim)
+            .find(|name| !name.is_empty() && name.len() <= MAX_USER_LEN)
+            .map(|name| User(String::from(name)))
+            .unwrap_or_default()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns true if the user is not provided by the proxy, the anonymous users
+    /// share the settings and may not send the books
+    pub fn is_anonymous(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The addresses of the reverse proxies which provide the user name and the forwarded headers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<IpAddr>);
+impl TrustedProxies {
+    /// Parse the comma separated addresses, the invalid ones are skipped
+    pub fn new(addresses: &str) -> Self {
+        let addresses = addresses
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .filter_map(|address| {
+                address
+                    .parse::<IpAddr>()
+                    .inspect_err(|err| warn!("The proxy address {address} is skipped: {err}"))
+                    .ok()
+            })
+            .map(|address| address.to_canonical())
+            .collect();
+        TrustedProxies(addresses)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, address: IpAddr) -> bool {
+        self.0.contains(&address.to_canonical())
+    }
+}
+
+/// Returns true if the request comes from one of the `TrustedProxies` of the application
+pub fn from_trusted_proxy(req: &HttpRequest) -> bool {
+    match (req.app_data::<TrustedProxies>(), req.peer_addr()) {
+        (Some(proxies), Some(peer)) => proxies.contains(peer.ip()),
+        _ => false,
+    }
+}
+
+/// The user is anonymous if the request does not come from a trusted proxy
+impl FromRequest for User {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        match from_trusted_proxy(req) {
+            true => ready(Ok(User::from_headers(req.headers()))),
+            false => ready(Ok(User::default())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_proxies() {
+        let proxies = TrustedProxies::new("127.0.0.1, ::1,proxy");
+        assert!(proxies.contains("127.0.0.1".parse().unwrap()));
+        assert!(proxies.contains("::ffff:127.0.0.1".parse().unwrap()));
+        assert!(proxies.contains("::1".parse().unwrap()));
+        assert!(!proxies.contains("10.0.0.1".parse().unwrap()));
+        assert!(TrustedProxies::new("").is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! Watching the library directory, the catalog database and other files for changes.
+//!
+//! The filesystem events are debounced: the callback is called once the watched
+//! files have been quiet for a while, so copying a big archive or rewriting
+//! the database triggers a single reload.
+use log::{debug, error, info};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const DEBOUNCE: Duration = Duration::from_secs(3);
+
+/// Returns the path of the database file from the SQLite URI like `file:/books.db?mode=ro`
+pub fn database_path(database: &str) -> PathBuf {
+    let path = database.strip_prefix("file:").unwrap_or(database);
+    let path = path.split('?').next().unwrap_or_default();
+    PathBuf::from(path)
+}
+
+/// Returns true if the event is not just reading and is about the file matching the predicate
+fn is_relevant<P: Fn(&Path) -> bool>(event: &Event, predicate: P) -> bool {
+    !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|path| predicate(path))
+}
+
+/// Files are replaced by renaming, so their directories are watched instead of the files
+fn parent(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Call `on_change` once the relevant events stop coming for the debounce period
+fn debounce<P, F>(rx: mpsc::Receiver<notify::Result<Event>>, predicate: P, on_change: F)
+where
+    P: Fn(&Path) -> bool + Send + 'static,
+    F: Fn() + Send + 'static,
+{
+    thread::spawn(move || {
+        let relevant = |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                debug!("{event:?}");
+                is_relevant(&event, &predicate)
+            }
+            Err(err) => {
+                error!("{err}");
+                false
+            }
+        };
+        while let Ok(event) = rx.recv() {
+            if !relevant(event) {
+                continue;
+            }
+            loop {
+                match rx.recv_timeout(DEBOUNCE) {
+                    Ok(event) => {
+                        relevant(event);
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            on_change();
+        }
+    });
+}
+
+/// Watch the library directory and the database file, the returned watcher must be kept alive
+pub fn watch<F>(library: &Path, database: &Path, on_change: F) -> anyhow::Result<RecommendedWatcher>
+where
+    F: Fn() + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    let db_dir = parent(database);
+    let db_name = database.file_name().map(OsString::from);
+    watcher.watch(library, RecursiveMode::NonRecursive)?;
+    if db_dir != library {
+        watcher.watch(&db_dir, RecursiveMode::NonRecursive)?;
+    }
+    info!("Watching {} and {}", library.display(), database.display());
+
+    let predicate = move |path: &Path| {
+        let is_archive = path.extension().is_some_and(|ext| ext == "zip");
+        is_archive || (db_name.is_some() && path.file_name() == db_name.as_deref())
+    };
+    debounce(rx, predicate, move || {
+        info!("The library has been changed");
+        on_change();
+    });
+    Ok(watcher)
+}
+
+/// Watch the files, the returned watcher must be kept alive
+pub fn watch_files<F>(files: &[PathBuf], on_change: F) -> anyhow::Result<RecommendedWatcher>
+where
+    F: Fn() + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    let mut dirs = files.iter().map(|file| parent(file)).collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+    for dir in dirs.iter() {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    info!("Watching {files:?}");
+
+    let names = files
+        .iter()
+        .filter_map(|file| file.file_name().map(OsString::from))
+        .collect::<Vec<_>>();
+    let predicate = move |path: &Path| {
+        path.file_name()
+            .is_some_and(|name| names.iter().any(|n| n == name))
+    };
+    debounce(rx, predicate, on_change);
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_path() {
+        let path = database_path("file:/lib.rus.ec/books.db?mode=ro");
+        assert_eq!(PathBuf::from("/lib.rus.ec/books.db"), path);
+        assert_eq!(PathBuf::from("books.db"), database_path("books.db"));
+    }
+}